@@ -5,6 +5,7 @@
 #include <sys/un.h>
 #include <unistd.h>
 #include <filesystem>
+#include <chrono>
 
 #include "utils.hpp"
 
@@ -12,12 +13,120 @@
 #define VERSION ""
 #endif
 
-void handle_plugin_event(PluginEvent e) {
+// the socket thread must not touch compositor state. so the main thread keeps a copy of
+// what the daemon might ask for.
+std::mutex dragged_window_mutex;
+std::string dragged_window;
+
+// called on the main thread
+void update_dragged_window() {
+    std::string address;
+    if (g_pInputManager->m_dragMode == MBIND_MOVE) {
+        if (const auto PWINDOW = g_pInputManager->m_currentlyDraggedWindow.lock()) {
+            address = std::format("0x{:x}", (uintptr_t)PWINDOW.get());
+        }
+    }
+    std::lock_guard lock(dragged_window_mutex);
+    dragged_window = address;
+}
+
+std::string get_dragged_window() {
+    std::lock_guard lock(dragged_window_mutex);
+    return dragged_window;
+}
+
+std::atomic<uint32_t> modifiers = 0;
+
+// called on the main thread
+void update_modifiers() {
+    modifiers = g_pInputManager->getModsFromAllKBs();
+}
+
+// keep in sync with PLUGIN_PROTOCOL_VERSION in src/plugin.rs
+#define KOOL_PLUGIN_PROTOCOL_VERSION 1
+
+std::string plugin_err(std::string msg) {
+    return "{\"type\":\"err\",\"message\":\"" + json_escape(msg) + "\"}\n";
+}
+
+std::optional<Animation> parse_animation(const std::string& name) {
+    if (name == "none") return Animation::None;
+    if (name == "left") return Animation::Left;
+    if (name == "right") return Animation::Right;
+    if (name == "up") return Animation::Up;
+    if (name == "down") return Animation::Down;
+    if (name == "fade") return Animation::Fade;
+    return std::nullopt;
+}
+
+// returns the line to reply with. see PluginRequest in src/plugin.rs
+std::string handle_plugin_request(const std::string& line) {
+    auto req = parse_flat_json(line);
+    if (!req) {
+        return plugin_err("could not parse request: " + line);
+    }
+    auto type = req->contains("type") ? req->at("type") : "";
+
+    if (type == "hello") {
+        // the daemon decides what to do if the protocol does not match
+        return std::format("{{\"type\":\"hello\",\"protocol\":{},\"version\":\"{}\",\"capabilities\":"
+                           "[\"set_animation\",\"animation_duration\",\"animation_curve\",\"dragged_window\",\"modifiers\"]}}\n",
+                           KOOL_PLUGIN_PROTOCOL_VERSION, json_escape(VERSION));
+    } else if (type == "set_animation") {
+        auto anim = parse_animation(req->contains("animation") ? req->at("animation") : "");
+        if (!anim) {
+            return plugin_err("unknown animation");
+        }
+        int duration = -1;
+        if (req->contains("duration")) {
+            try {
+                duration = std::stoi(req->at("duration"));
+            } catch (const std::exception& e) {
+                return plugin_err("invalid duration: " + req->at("duration"));
+            }
+        }
+        std::lock_guard lock(anim_mutex);
+        anim_dir = *anim;
+        anim_duration = duration;
+        anim_curve = req->contains("curve") ? req->at("curve") : "";
+    } else if (type == "dragged_window") {
+        auto address = get_dragged_window();
+        if (address.empty()) {
+            return "{\"type\":\"dragged_window\",\"address\":null}\n";
+        }
+        return "{\"type\":\"dragged_window\",\"address\":\"" + address + "\"}\n";
+    } else if (type == "modifiers") {
+        uint32_t mods = modifiers;
+        return "{\"type\":\"modifiers\",\"mask\":" + std::to_string(mods) + "}\n";
+    } else {
+        return plugin_err("unknown request: " + type);
+    }
+    return "{\"type\":\"ok\"}\n";
+}
+
+// daemons from older versions send a bare PluginEvent and understand bare Message replies.
+// returns the line to reply with
+std::string handle_plugin_event(PluginEvent e) {
     switch (e) {
+        case PluginEvent::QueryDraggedWindow: {
+            auto address = get_dragged_window();
+            if (address.empty()) {
+                return "\"IpcOk\"\n";
+            }
+            return "{\"IpcMessage\":\"" + address + "\"}\n";
+        } break;
+        case PluginEvent::QueryModifiers: {
+            uint32_t mods = modifiers;
+            return "{\"IpcMessage\":\"" + std::to_string(mods) + "\"}\n";
+        } break;
         default: {
+            std::lock_guard lock(anim_mutex);
             anim_dir = static_cast<Animation>(e);
+            anim_duration = -1;
+            anim_curve = "";
         } break;
     }
+    return "\"IpcOk\"\n";
 }
 
 void sendstr(int sockfd, const char* buf) {
@@ -51,9 +160,14 @@ void socket_connect(int clientfd) {
         std::string line;
         while (std::getline(iss, line)) {
             try {
-                auto e = static_cast<PluginEvent>(std::stoi(line));
-                handle_plugin_event(e);
-                sendstr(clientfd, "\"IpcOk\"\n");
+                std::string reply;
+                if (line.starts_with("{")) {
+                    reply = handle_plugin_request(line);
+                } else {
+                    auto e = static_cast<PluginEvent>(std::stoi(line));
+                    reply = handle_plugin_event(e);
+                }
+                sendstr(clientfd, reply.c_str());
             } catch (const std::exception& e) {
                 std::cerr << "Error parsing socket data: " << e.what() << std::endl;
                 continue;
@@ -64,6 +178,8 @@ void socket_connect(int clientfd) {
     }
 }
 
+void send_daemon_command(std::string cmd);
+
 void socket_serve() {
     sockfd = socket(AF_UNIX, SOCK_STREAM, 0);
     if (sockfd < 0) {
@@ -86,6 +202,9 @@ void socket_serve() {
     // what to do other than this.
     listen(sockfd, 5);
 
+    // the daemon caches whether the plugin is around. let it know that we are now.
+    send_daemon_command("\"PluginLoaded\"");
+
     pollfd fd;
     fd.fd = sockfd;
     fd.events = POLLIN;
@@ -109,6 +228,14 @@ void socket_serve() {
             throw_err_notif("Error accepting connection");
         }
 
+        struct ucred cred;
+        socklen_t len = sizeof(cred);
+        if (getsockopt(clientfd, SOL_SOCKET, SO_PEERCRED, &cred, &len) < 0 || cred.uid != getuid()) {
+            std::cerr << "[hyprkool] refusing connection from another user" << std::endl;
+            close(clientfd);
+            continue;
+        }
+
         socket_connect(clientfd);
         close(clientfd);
     }
@@ -125,21 +252,192 @@ void safe_socket_serve() {
     }
 }
 
+// keep in sync with PROTOCOL_VERSION in src/event.rs
+#define KOOL_PROTOCOL_VERSION 3
+
+// fire and forget a command to the hyprkool daemon. the reply is read on a separate thread
+// so that the compositor does not wait on the daemon.
+void send_daemon_command(std::string cmd) {
+    auto msg = std::format("{{\"id\":0,\"message\":{{\"Hello\":{{\"protocol\":{},\"version\":\"{}\"}}}}}}\n",
+                           KOOL_PROTOCOL_VERSION, VERSION);
+    msg += "{\"id\":1,\"message\":{\"Command\":" + cmd + "}}\n";
+    std::thread([msg]() {
+        int fd = socket(AF_UNIX, SOCK_STREAM, 0);
+        if (fd < 0) {
+            return;
+        }
+
+        struct sockaddr_un addr;
+        addr.sun_family = AF_UNIX;
+        strncpy(addr.sun_path, kool_sock_path.c_str(), sizeof(addr.sun_path) - 1);
+        if (connect(fd, (struct sockaddr*)&addr, sizeof(addr)) < 0) {
+            std::cerr << "[hyprkool] could not connect to hyprkool daemon" << std::endl;
+            close(fd);
+            return;
+        }
+
+        if (send(fd, msg.c_str(), msg.size(), MSG_NOSIGNAL) >= 0) {
+            shutdown(fd, SHUT_WR);
+            char buffer[256];
+            while (read(fd, buffer, sizeof(buffer)) > 0) {
+            }
+        }
+        close(fd);
+    }).detach();
+}
+
+inline SP<HOOK_CALLBACK_FN> g_pMouseAxisCallback;
+inline SP<HOOK_CALLBACK_FN> g_pMouseMoveCallback;
+inline SP<HOOK_CALLBACK_FN> g_pMouseButtonCallback;
+inline SP<HOOK_CALLBACK_FN> g_pKeyPressCallback;
+std::chrono::steady_clock::time_point last_scroll_switch;
+
+// bars, docks, notifications etc. are layer surfaces above the windows. scrolls on them are for them.
+// (wallpapers are below the windows, so scrolling on the empty desktop still works)
+bool top_layer_surface_under_cursor() {
+    const auto focus = g_pSeatManager->m_state.pointerFocus.lock();
+    if (!focus) {
+        return false;
+    }
+    const auto surface = CWLSurface::fromResource(focus);
+    if (!surface) {
+        return false;
+    }
+    const auto layer = surface->getLayer();
+    return layer && layer->m_layer >= ZWLR_LAYER_SHELL_V1_LAYER_TOP;
+}
+
+void on_mouse_axis(void* self, SCallbackInfo& info, std::any param) {
+    if (!g_KoolConfig.scroll_on_edge && !g_KoolConfig.scroll_on_empty_desktop) {
+        return;
+    }
+
+    auto e = std::any_cast<IPointer::SAxisEvent>(std::any_cast<std::unordered_map<std::string, std::any>>(param)["event"]);
+    if (e.axis != WL_POINTER_AXIS_VERTICAL_SCROLL || e.delta == 0) {
+        return;
+    }
+
+    const auto PMONITOR = g_pCompositor->getMonitorFromCursor();
+    if (!PMONITOR) {
+        return;
+    }
+
+    // games and videos want their scrolls, even on the edges
+    const auto PWORKSPACE = PMONITOR->m_activeWorkspace;
+    if (PWORKSPACE && PWORKSPACE->m_hasFullscreenWindow && PWORKSPACE->m_fullscreenMode == FSMODE_FULLSCREEN) {
+        return;
+    }
+    if (top_layer_surface_under_cursor()) {
+        return;
+    }
+
+    const auto pos = g_pInputManager->getMouseCoordsInternal();
+    const auto w = g_KoolConfig.edge_width;
+    const auto tl = PMONITOR->m_position;
+    const auto br = PMONITOR->m_position + PMONITOR->m_size - Vector2D{1, 1};
+    // scrolling up moves left/up
+    const int d = e.delta < 0 ? -1 : 1;
+
+    int x = 0, y = 0;
+    if (g_KoolConfig.scroll_on_edge && (pos.x <= tl.x + w || pos.x >= br.x - w)) {
+        x = d;
+    } else if (g_KoolConfig.scroll_on_edge && (pos.y <= tl.y + w || pos.y >= br.y - w)) {
+        y = d;
+    } else if (g_KoolConfig.scroll_on_empty_desktop &&
+               !g_pCompositor->vectorToWindowUnified(pos, RESERVED_EXTENTS | INPUT_EXTENTS | ALLOW_FLOATING)) {
+        x = d;
+    } else {
+        return;
+    }
+
+    // the scroll is not cancelled. only the daemon knows if it will switch, and the window under
+    // the cursor should still get the scroll if it does not.
+
+    // touchpads send a lot of small scroll events
+    const auto now = std::chrono::steady_clock::now();
+    if (now - last_scroll_switch < std::chrono::milliseconds(200)) {
+        return;
+    }
+    last_scroll_switch = now;
+
+    // the daemon decides if edge switching is allowed right now
+    send_daemon_command(std::format("{{\"MouseScroll\":{{\"x\":{},\"y\":{}}}}}", x, y));
+}
+
+// a window can only reach the edges while the mouse moves. this runs before hyprland handles the
+// motion, but a drag starts on a button press, so it is already known here.
+void on_mouse_move(void* self, SCallbackInfo& info, std::any param) {
+    update_dragged_window();
+    update_modifiers();
+}
+
+void on_key_press(void* self, SCallbackInfo& info, std::any param) {
+    // this runs before hyprland applies the key to the modifiers. read them once it has.
+    g_pEventLoopManager->doLater(update_modifiers);
+}
+
+void on_mouse_button(void* self, SCallbackInfo& info, std::any param) {
+    auto e = std::any_cast<IPointer::SButtonEvent>(param);
+    // hyprland ends the drag after this callback
+    if (e.state == WL_POINTER_BUTTON_STATE_RELEASED) {
+        std::lock_guard lock(dragged_window_mutex);
+        dragged_window = "";
+    }
+}
+
+void init_callbacks() {
+    g_pMouseAxisCallback = HyprlandAPI::registerCallbackDynamic(PHANDLE, "mouseAxis", on_mouse_axis);
+    g_pMouseMoveCallback = HyprlandAPI::registerCallbackDynamic(PHANDLE, "mouseMove", on_mouse_move);
+    g_pMouseButtonCallback = HyprlandAPI::registerCallbackDynamic(PHANDLE, "mouseButton", on_mouse_button);
+    g_pKeyPressCallback = HyprlandAPI::registerCallbackDynamic(PHANDLE, "keyPress", on_key_press);
+}
+
 inline CFunctionHook* g_pWorkAnimHook = nullptr;
 
 using origStartAnim = void(*)(CDesktopAnimationManager*, PHLWORKSPACE, CDesktopAnimationManager::eAnimationType, bool, bool);
 constexpr auto _ = static_cast<void (CDesktopAnimationManager::*)(PHLWORKSPACE, CDesktopAnimationManager::eAnimationType, bool, bool)>
                         (&CDesktopAnimationManager::startAnimation);
 
+// (speed, bezier) from hyprland's config while they are overridden
+std::optional<std::pair<float, std::string>> overridden_anim;
+
 void hk_workspace_anim(CDesktopAnimationManager* thisptr, PHLWORKSPACE ws, CDesktopAnimationManager::eAnimationType type, bool left, bool instant) {
     Hyprutils::Memory::CWeakPointer<Hyprutils::Animation::SAnimationPropertyConfig> conf = ws->m_alpha->getConfig();
 
+    Animation dir;
+    int duration;
+    std::string curve;
+    {
+        std::lock_guard lock(anim_mutex);
+        dir = anim_dir;
+        duration = anim_duration;
+        curve = anim_curve;
+    }
+
     std::string style;
     if (const auto pconfig = conf.lock()) {
         if (const auto pvalues = pconfig->pValues.lock()) {
             style = pvalues->internalStyle;
 
-            switch (anim_dir) {
+            // speed and bezier are read on every animation tick. so unlike the style, they can't be
+            // restored right after the animation starts. they stay overridden till a request without them.
+            if (duration >= 0 || !curve.empty()) {
+                if (!overridden_anim) {
+                    overridden_anim = {pvalues->internalSpeed, pvalues->internalBezier};
+                }
+                // speed is in ds
+                pvalues->internalSpeed = duration > 0 ? duration / 100.f : overridden_anim->first;
+                pvalues->internalBezier = !curve.empty() ? curve : overridden_anim->second;
+                if (duration == 0) {
+                    instant = true;
+                }
+            } else if (overridden_anim) {
+                pvalues->internalSpeed = overridden_anim->first;
+                pvalues->internalBezier = overridden_anim->second;
+                overridden_anim.reset();
+            }
+
+            switch (dir) {
                 case Animation::None: {
                     instant = true;
                 } break;
@@ -211,8 +509,10 @@ APICALL EXPORT PLUGIN_DESCRIPTION_INFO PLUGIN_INIT(HANDLE handle) {
     }
 
     sock_path = get_socket_path();
+    kool_sock_path = std::filesystem::path(sock_path).parent_path() / "kool.sock";
 
     init_hooks();
+    init_callbacks();
     init_hypr_config();
     set_config();
     // NOTE: throwing not allowed in another thread
@@ -1,12 +1,17 @@
 #include <toml++/toml.hpp>
 #include <filesystem>
+#include <unistd.h>
 
 #include "utils.hpp"
 
+std::mutex anim_mutex;
 Animation anim_dir = Animation::None;
+int anim_duration = -1;
+std::string anim_curve;
 
 inline HANDLE PHANDLE = nullptr;
 std::string sock_path;
+std::string kool_sock_path;
 bool exit_flag = false;
 int sockfd = -1;
 std::thread sock_thread;
@@ -52,6 +57,9 @@ void _set_config() {
     // default values
     g_KoolConfig.workspaces_x = 2;
     g_KoolConfig.workspaces_y = 2;
+    g_KoolConfig.edge_width = 0;
+    g_KoolConfig.scroll_on_edge = false;
+    g_KoolConfig.scroll_on_empty_desktop = false;
 
     const auto HOME = getenv("HOME");
     auto path = std::string(HOME) + "/.config/hypr/hyprkool.toml";
@@ -60,6 +68,12 @@ void _set_config() {
     }
     // NOTE: parsing error is nicely displayed in the notif area
     auto manifest = toml::parse_file(path);
+
+    auto mouse = manifest["daemon"]["mouse"];
+    g_KoolConfig.edge_width = mouse["edge_width"].value_or(0);
+    g_KoolConfig.scroll_on_edge = mouse["scroll_on_edge"].value_or(false);
+    g_KoolConfig.scroll_on_empty_desktop = mouse["scroll_on_empty_desktop"].value_or(false);
+
     auto workspaces = manifest["workspaces"].as_array();
     if (!workspaces) {
         return;
@@ -85,13 +99,165 @@ std::string get_socket_path() {
     if (!ISIG) {
         throw_err_notif("HYPRLAND_INSTANCE_SIGNATURE not set! (is hyprland running?)");
     }
-    auto sock_path = "/tmp/hyprkool/" + std::string(ISIG);
+    const auto RUNTIME_DIR = getenv("XDG_RUNTIME_DIR");
+    auto kool_dir = RUNTIME_DIR ? std::string(RUNTIME_DIR) : "/run/user/" + std::to_string(getuid());
+    kool_dir += "/hyprkool";
+    auto sock_path = kool_dir + "/" + std::string(ISIG);
     if (!std::filesystem::exists(sock_path)) {
         if (!std::filesystem::create_directories(sock_path)) {
             throw_err_notif("could not create directory");
         }
     }
+    // only the user running hyprland gets to talk to the plugin
+    std::filesystem::permissions(kool_dir, std::filesystem::perms::owner_all, std::filesystem::perm_options::replace);
+    std::filesystem::permissions(sock_path, std::filesystem::perms::owner_all, std::filesystem::perm_options::replace);
     sock_path += "/plugin.sock";
     return sock_path;
 }
 
+static void skip_ws(const std::string& s, size_t& i) {
+    while (i < s.size() && std::isspace((unsigned char)s[i])) {
+        i++;
+    }
+}
+
+static void push_utf8(std::string& out, unsigned cp) {
+    if (cp < 0x80) {
+        out += (char)cp;
+    } else if (cp < 0x800) {
+        out += (char)(0xC0 | (cp >> 6));
+        out += (char)(0x80 | (cp & 0x3F));
+    } else {
+        out += (char)(0xE0 | (cp >> 12));
+        out += (char)(0x80 | ((cp >> 6) & 0x3F));
+        out += (char)(0x80 | (cp & 0x3F));
+    }
+}
+
+static std::optional<std::string> parse_string(const std::string& s, size_t& i) {
+    if (i >= s.size() || s[i] != '"') {
+        return std::nullopt;
+    }
+    i++;
+    std::string out;
+    while (i < s.size()) {
+        char c = s[i++];
+        if (c == '"') {
+            return out;
+        }
+        if (c != '\\') {
+            out += c;
+            continue;
+        }
+        if (i >= s.size()) {
+            return std::nullopt;
+        }
+        char e = s[i++];
+        switch (e) {
+            case '"':
+            case '\\':
+            case '/': out += e; break;
+            case 'b': out += '\b'; break;
+            case 'f': out += '\f'; break;
+            case 'n': out += '\n'; break;
+            case 'r': out += '\r'; break;
+            case 't': out += '\t'; break;
+            case 'u': {
+                if (i + 4 > s.size()) {
+                    return std::nullopt;
+                }
+                auto hex = s.substr(i, 4);
+                if (!std::all_of(hex.begin(), hex.end(), [](char h) { return std::isxdigit((unsigned char)h); })) {
+                    return std::nullopt;
+                }
+                // NOTE: surrogate pairs are not combined. nothing we receive needs them.
+                push_utf8(out, std::stoul(hex, nullptr, 16));
+                i += 4;
+            } break;
+            default: return std::nullopt;
+        }
+    }
+    return std::nullopt;
+}
+
+std::optional<FlatJson> parse_flat_json(const std::string& s) {
+    FlatJson obj;
+    size_t i = 0;
+    skip_ws(s, i);
+    if (i >= s.size() || s[i] != '{') {
+        return std::nullopt;
+    }
+    i++;
+    skip_ws(s, i);
+    if (i < s.size() && s[i] == '}') {
+        return obj;
+    }
+    while (true) {
+        skip_ws(s, i);
+        auto key = parse_string(s, i);
+        if (!key) {
+            return std::nullopt;
+        }
+        skip_ws(s, i);
+        if (i >= s.size() || s[i] != ':') {
+            return std::nullopt;
+        }
+        i++;
+        skip_ws(s, i);
+        if (i >= s.size()) {
+            return std::nullopt;
+        }
+        if (s[i] == '"') {
+            auto value = parse_string(s, i);
+            if (!value) {
+                return std::nullopt;
+            }
+            obj[*key] = *value;
+        } else {
+            size_t start = i;
+            while (i < s.size() && s[i] != ',' && s[i] != '}' && !std::isspace((unsigned char)s[i])) {
+                i++;
+            }
+            auto value = s.substr(start, i - start);
+            if (value.empty() || value[0] == '{' || value[0] == '[') {
+                return std::nullopt;
+            }
+            if (value != "null") {
+                obj[*key] = value;
+            }
+        }
+        skip_ws(s, i);
+        if (i >= s.size()) {
+            return std::nullopt;
+        }
+        if (s[i] == ',') {
+            i++;
+            continue;
+        }
+        if (s[i] == '}') {
+            return obj;
+        }
+        return std::nullopt;
+    }
+}
+
+std::string json_escape(const std::string& s) {
+    std::string out;
+    for (char c : s) {
+        switch (c) {
+            case '"': out += "\\\""; break;
+            case '\\': out += "\\\\"; break;
+            case '\n': out += "\\n"; break;
+            case '\r': out += "\\r"; break;
+            case '\t': out += "\\t"; break;
+            default: {
+                if ((unsigned char)c < 0x20) {
+                    out += std::format("\\u{:04x}", (unsigned char)c);
+                } else {
+                    out += c;
+                }
+            } break;
+        }
+    }
+    return out;
+}
@@ -1,10 +1,21 @@
 #pragma once
 
+#include <atomic>
 #include <ctime>
+#include <mutex>
+#include <optional>
 #include <thread>
+#include <unordered_map>
 #include <hyprland/src/plugins/PluginAPI.hpp>
 #include <hyprland/src/desktop/Workspace.hpp>
 #include <hyprland/src/managers/animation/DesktopAnimationManager.hpp>
+#include <hyprland/src/managers/input/InputManager.hpp>
+#include <hyprland/src/devices/IPointer.hpp>
+#include <hyprland/src/Compositor.hpp>
+#include <hyprland/src/managers/SeatManager.hpp>
+#include <hyprland/src/managers/eventLoop/EventLoopManager.hpp>
+#include <hyprland/src/desktop/WLSurface.hpp>
+#include <hyprland/src/desktop/LayerSurface.hpp>
 
 enum Animation {
     None = 0,
@@ -21,11 +32,20 @@ enum PluginEvent {
     AnimationUp = 3,
     AnimationDown = 4,
     AnimationFade = 5,
+    QueryDraggedWindow = 6,
+    QueryModifiers = 7,
 };
+// guards anim_dir, anim_duration and anim_curve. they are set on the socket thread and read on the main thread
+extern std::mutex anim_mutex;
 extern Animation anim_dir;
+// in ms. hyprland's configured speed if < 0
+extern int anim_duration;
+// hyprland's configured bezier if empty
+extern std::string anim_curve;
 
 extern HANDLE PHANDLE;
 extern std::string sock_path;
+extern std::string kool_sock_path;
 extern bool exit_flag;
 extern int sockfd;
 extern std::thread sock_thread;
@@ -36,9 +56,18 @@ void throw_err_notif(std::string msg);
 struct KoolConfig {
     int workspaces_x;
     int workspaces_y;
+    int edge_width;
+    bool scroll_on_edge;
+    bool scroll_on_empty_desktop;
 };
 extern KoolConfig g_KoolConfig;
 
 void _set_config();
 void set_config();
 std::string get_socket_path();
+
+// a json object without nested values. strings are unescaped, other values are kept as written
+// and null values are left out.
+using FlatJson = std::unordered_map<std::string, std::string>;
+std::optional<FlatJson> parse_flat_json(const std::string& s);
+std::string json_escape(const std::string& s);
@@ -0,0 +1,211 @@
+use std::path::PathBuf;
+
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use crate::event::get_socket_dir;
+use crate::hyprctl::animation;
+use crate::hyprctl::keyword;
+use crate::hyprctl::workspace_id;
+use crate::hyprctl::AnimationConfig;
+use crate::plugin::Animation;
+
+// NOTE: without the plugin, hyprland picks the direction of slide animations by comparing
+//  workspace ids (see CMonitor::changeWorkspace in hyprland). hyprkool workspaces are named
+//  workspaces, and their ids only depend on the order they were created in. so hyprkool can't
+//  choose the direction. it slides only when hyprland would slide the right way, and fades otherwise.
+
+/// sets workspace animations through `hyprctl keyword` (for when the plugin can't be used)
+#[derive(Default)]
+pub struct KeywordAnimator {
+    /// workspace animation from hyprland's config
+    base: Option<AnimationConfig>,
+    /// what we last set it to
+    current: Option<String>,
+}
+
+impl KeywordAnimator {
+    /// hyprland's config was reloaded. it might have changed, and our changes are gone.
+    pub fn invalidate(&mut self) {
+        self.base = None;
+        self.current = None;
+    }
+
+    /// duration (in ms) and curve are taken from hyprland's config if not set.
+    /// `switch` is the (from, to) workspace names. slides fade if it's not known.
+    pub async fn set(
+        &mut self,
+        anim: Animation,
+        duration: Option<u32>,
+        curve: Option<String>,
+        switch: Option<(&str, &str)>,
+    ) -> Result<()> {
+        let base = match &self.base {
+            Some(base) => base,
+            None => self.base.insert(base_animation().await?),
+        };
+        if !base.enabled {
+            return Ok(());
+        }
+
+        let ids = match (anim, switch) {
+            (
+                Animation::Left | Animation::Right | Animation::Up | Animation::Down,
+                Some((from, to)),
+            ) => workspace_id(from).await?.zip(workspace_id(to).await?),
+            _ => None,
+        };
+        let style = match anim {
+            _ if duration == Some(0) => None,
+            anim => keyword_style(anim, ids),
+        };
+        let value = match style {
+            Some(style) => {
+                // speed is in ds
+                let speed = duration.map(|d| d as f32 / 100.0).unwrap_or(base.speed);
+                let curve = curve.unwrap_or_else(|| base.bezier.clone());
+                format!("workspaces,1,{},{},{}", speed, curve, style)
+            }
+            None => "workspaces,0".to_string(),
+        };
+        if self.current.as_ref() == Some(&value) {
+            return Ok(());
+        }
+
+        // saved before hyprland has it, so that nothing is left that could be mistaken for the config
+        SavedAnimation {
+            base: base.clone(),
+            written: value.clone(),
+        }
+        .save()?;
+        keyword("animation", &value).await?;
+        self.current = Some(value);
+        Ok(())
+    }
+
+    /// puts hyprland's own workspace animation back (if hyprkool has changed it)
+    pub async fn restore(&mut self) -> Result<()> {
+        self.invalidate();
+        let Some(saved) = SavedAnimation::load() else {
+            return Ok(());
+        };
+        if keyword_value(&animation("workspaces").await?) == saved.written {
+            keyword("animation", &keyword_value(&saved.base)).await?;
+        }
+        SavedAnimation::remove()
+    }
+}
+
+/// hyprland's workspace animation from its config. the live value can't be trusted if hyprkool
+/// has set it before (maybe in an earlier run).
+async fn base_animation() -> Result<AnimationConfig> {
+    let live = animation("workspaces").await?;
+    match SavedAnimation::load() {
+        // if it's not what we wrote, hyprland's config was reloaded (or someone else set it)
+        Some(saved) if keyword_value(&live) == saved.written => Ok(saved.base),
+        _ => Ok(live),
+    }
+}
+
+/// the value for `hyprctl keyword animation` that sets this workspace animation
+fn keyword_value(anim: &AnimationConfig) -> String {
+    if anim.enabled {
+        format!("workspaces,1,{},{},{}", anim.speed, anim.bezier, anim.style)
+    } else {
+        "workspaces,0".to_string()
+    }
+}
+
+/// what hyprkool changed hyprland's workspace animation from and to.
+/// kept in a file, as hyprkool's changes outlive the process that made them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct SavedAnimation {
+    base: AnimationConfig,
+    written: String,
+}
+
+impl SavedAnimation {
+    fn path() -> Result<PathBuf> {
+        Ok(get_socket_dir()?.join("keyword-animation.json"))
+    }
+
+    fn load() -> Option<Self> {
+        let s = std::fs::read_to_string(Self::path().ok()?).ok()?;
+        serde_json::from_str(&s).ok()
+    }
+
+    fn save(&self) -> Result<()> {
+        std::fs::write(Self::path()?, serde_json::to_string(self)?)?;
+        Ok(())
+    }
+
+    fn remove() -> Result<()> {
+        match std::fs::remove_file(Self::path()?) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// hyprland's workspace animation style for this animation. None disables the animation.
+/// `ids` are the (from, to) workspace ids. slides fade if hyprland would slide the wrong way
+/// (or if the target workspace does not exist yet. it gets an id when it is created)
+fn keyword_style(anim: Animation, ids: Option<(i64, i64)>) -> Option<&'static str> {
+    // hyprland slides right (and down) when moving to a workspace with a bigger id.
+    // (ignoring animations:workspace_wraparound)
+    let forward = ids.map(|(from, to)| to > from);
+    match anim {
+        Animation::None => None,
+        Animation::Right if forward == Some(true) => Some("slide"),
+        Animation::Left if forward == Some(false) => Some("slide"),
+        Animation::Down if forward == Some(true) => Some("slidevert"),
+        Animation::Up if forward == Some(false) => Some("slidevert"),
+        _ => Some("fade"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slides_only_in_the_right_direction() {
+        assert_eq!(keyword_style(Animation::Right, Some((3, 5))), Some("slide"));
+        assert_eq!(keyword_style(Animation::Left, Some((3, 5))), Some("fade"));
+        assert_eq!(
+            keyword_style(Animation::Left, Some((-1340, -1342))),
+            Some("slide")
+        );
+        assert_eq!(
+            keyword_style(Animation::Down, Some((1, 2))),
+            Some("slidevert")
+        );
+        assert_eq!(keyword_style(Animation::Up, Some((1, 2))), Some("fade"));
+        assert_eq!(
+            keyword_style(Animation::Up, Some((2, 1))),
+            Some("slidevert")
+        );
+    }
+
+    #[test]
+    fn keyword_values_match_what_hyprland_reports() {
+        let anim: AnimationConfig = serde_json::from_str(
+            r#"{"name":"workspaces","overridden":true,"bezier":"default","enabled":true,"speed":2.5,"style":"slide"}"#,
+        )
+        .unwrap();
+        assert_eq!(keyword_value(&anim), "workspaces,1,2.5,default,slide");
+
+        let anim = AnimationConfig {
+            enabled: false,
+            ..anim
+        };
+        assert_eq!(keyword_value(&anim), "workspaces,0");
+    }
+
+    #[test]
+    fn fades_when_ids_are_unknown() {
+        assert_eq!(keyword_style(Animation::Right, None), Some("fade"));
+        assert_eq!(keyword_style(Animation::Fade, None), Some("fade"));
+        assert_eq!(keyword_style(Animation::None, Some((1, 2))), None);
+    }
+}
@@ -1,16 +1,18 @@
 use std::sync::Arc;
 
-use anyhow::Result;
-use clap::{arg, command, Subcommand};
+use anyhow::{anyhow, Result};
+use clap::{Parser, Subcommand, ValueEnum};
 use serde::{Deserialize, Serialize};
 use tokio::io::AsyncWriteExt;
-use tokio::net::UnixStream;
+use tokio::net::unix::OwnedWriteHalf;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
 use tokio::sync::Mutex;
+use tracing::error;
 
 use crate::event::KEvent;
 use crate::event::Message;
+use crate::event::Packet;
 use crate::info::InfoCommandContext;
 use crate::info::KInfoEvent;
 
@@ -18,6 +20,12 @@ use crate::info::KInfoEvent;
 pub enum InfoCommand {
     Submap,
 
+    /// shows if switching workspaces on screen edges is enabled / inhibited
+    EdgeSwitch,
+
+    /// streams typed json events about what changed (activity, workspace, named focus, ...)
+    Events,
+
     /// shows all info needed to create widgets for windows, workspaces, activities, monitors
     MonitorsAllInfo {
         /// try to find smallest icon bigger/equal to this size in px
@@ -39,7 +47,13 @@ impl InfoCommand {
             InfoCommand::MonitorsAllInfo { .. } => {
                 tx.send(KEvent::MonitorInfoRequested).await?;
             }
-            InfoCommand::Submap => {}
+            InfoCommand::EdgeSwitch => {
+                tx.send(KEvent::EdgeSwitchInfoRequested).await?;
+            }
+            InfoCommand::Submap => {
+                tx.send(KEvent::SubmapInfoRequested).await?;
+            }
+            InfoCommand::Events => {}
         }
 
         Ok(())
@@ -60,6 +74,14 @@ impl InfoCommand {
                 }
                 _ => Ok(None),
             },
+            InfoCommand::Events => match event {
+                KInfoEvent::Event(e) => Ok(Some(serde_json::to_string(&e)?)),
+                _ => Ok(None),
+            },
+            InfoCommand::EdgeSwitch => match event {
+                KInfoEvent::EdgeSwitch(status) => Ok(Some(serde_json::to_string(&status)?)),
+                _ => Ok(None),
+            },
             InfoCommand::MonitorsAllInfo {
                 window_icon_try_min_size,
                 window_icon_theme,
@@ -92,7 +114,7 @@ impl InfoCommand {
 
     pub async fn listen_loop(
         self,
-        mut sock: UnixStream,
+        mut out: InfoOutputStream,
         tx: mpsc::Sender<KEvent>,
         mut rx: broadcast::Receiver<KInfoEvent>,
         monitor: bool,
@@ -101,26 +123,25 @@ impl InfoCommand {
         // NOTE: DO NOT return errors other than socket errors
 
         if let Err(e) = self.fire_events(tx).await {
-            println!("error when firing info events: {:?}", e);
-            sock.write_all(&Message::IpcErr(format!("error: {}", e)).msg())
-                .await?;
-            sock.flush().await?;
+            error!("error when firing info events: {:?}", e);
+            out.write(Message::IpcErr(format!("error: {}", e))).await?;
             return Ok(());
         }
 
         loop {
             match self.listen(&mut rx, &info_ctx).await {
                 Ok(Some(msg)) => {
-                    sock.write_all(&Message::IpcMessage(msg).msg()).await?;
+                    out.write(Message::IpcMessage(msg)).await?;
+                }
+                Ok(None) => {
+                    // wait for an event relevant to this command
+                    continue;
                 }
-                Ok(None) => {}
                 Err(e) => {
-                    println!("error when listening for info messages: {:?}", e);
-                    sock.write_all(&Message::IpcErr(format!("error: {}", e)).msg())
-                        .await?;
+                    error!("error when listening for info messages: {:?}", e);
+                    out.write(Message::IpcErr(format!("error: {}", e))).await?;
                 }
             }
-            sock.flush().await?;
 
             if !monitor {
                 break;
@@ -131,10 +152,125 @@ impl InfoCommand {
     }
 }
 
+pub enum InfoOutputStream {
+    Stdout,
+    Sock { sock: OwnedWriteHalf, id: u64 },
+}
+
+impl InfoOutputStream {
+    async fn write(&mut self, message: Message) -> Result<()> {
+        match self {
+            Self::Stdout => match message {
+                Message::IpcMessage(msg) => println!("{}", msg),
+                Message::IpcErr(msg) => eprintln!("{}", msg),
+                Message::Error(e) => eprintln!("error: {}", e),
+                _ => {}
+            },
+            Self::Sock { sock, id } => {
+                sock.write_all(&Packet { id: *id, message }.msg()).await?;
+                sock.flush().await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Subcommand, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum QueryCommand {
+    /// focused monitor, activity and workspace
+    Current,
+    /// workspace set to a named focus
+    NamedFocus {
+        #[arg(short, long)]
+        name: String,
+    },
+    /// workspaces of all named focii
+    NamedFocii,
+}
+
+/// replaces {key} in the template with the value of that key in the json object.
+/// arrays of objects are formatted one per line.
+/// inserted values are never expanded again (a window title might contain braces)
+pub fn format_template(template: &str, value: &serde_json::Value) -> String {
+    use serde_json::Value;
+
+    match value {
+        Value::Array(values) => values
+            .iter()
+            .map(|v| format_template(template, v))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        Value::Object(map) => {
+            let mut out = String::new();
+            let mut rest = template;
+            while let Some(start) = rest.find('{') {
+                out.push_str(&rest[..start]);
+                rest = &rest[start..];
+                let field = rest
+                    .find('}')
+                    .and_then(|end| Some((end, map.get(&rest[1..end])?)));
+                match field {
+                    Some((end, v)) => {
+                        out.push_str(&template_value(v));
+                        rest = &rest[end + 1..];
+                    }
+                    None => {
+                        // not a key. keep it as it is
+                        out.push('{');
+                        rest = &rest[1..];
+                    }
+                }
+            }
+            out.push_str(rest);
+            out
+        }
+        v => v.to_string(),
+    }
+}
+
+fn template_value(value: &serde_json::Value) -> String {
+    use serde_json::Value;
+
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Array(a) => a
+            .iter()
+            .map(|v| match v {
+                Value::String(s) => s.clone(),
+                v => v.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join(","),
+        v => v.to_string(),
+    }
+}
+
+#[derive(ValueEnum, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ToggleAction {
+    Enable,
+    Disable,
+    Toggle,
+}
+
 #[derive(Subcommand, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Command {
     Daemon,
     DaemonQuit,
+    /// check if the daemon is running and show some diagnostics
+    Status,
+    /// sent by the plugin when it gets loaded
+    #[command(hide = true)]
+    PluginLoaded,
+    /// sent by the plugin when the mouse scrolls on a screen edge or on the empty desktop.
+    /// moves by (x, y) like switching on a screen edge, unless that is blocked right now
+    #[command(hide = true)]
+    MouseScroll {
+        #[arg(long, allow_negative_numbers = true)]
+        x: i32,
+        #[arg(long, allow_negative_numbers = true)]
+        y: i32,
+    },
     Info {
         #[command(subcommand)]
         command: InfoCommand,
@@ -285,4 +421,153 @@ pub enum Command {
         name: String,
     },
     ToggleOverview,
+    /// enable / disable switching workspaces when cursor touches screen edges (needs the daemon)
+    EdgeSwitch {
+        #[arg(value_enum)]
+        action: ToggleAction,
+    },
+    /// answer questions about the current state. prints json
+    Query {
+        #[command(subcommand)]
+        query: QueryCommand,
+
+        /// print using a template instead of json. eg. '{activity} {x},{y}'
+        /// keys are the fields of the json output
+        #[arg(long, short, global = true)]
+        format: Option<String>,
+    },
+    /// read commands from stdin (one per line) and run them over a single connection to the daemon.
+    /// prints the result of each line as json
+    Shell,
+    /// run multiple commands in one go. prints the result of each command as json
+    Batch {
+        /// json array of commands or a ';' separated list of commands
+        /// eg. "switch-to-activity -n work; switch-to-workspace-in-activity -n '(2 1)'"
+        commands: String,
+
+        /// don't run the remaining commands if one of them fails
+        #[arg(long, short = 'e', default_value_t = false)]
+        stop_on_error: bool,
+    },
+}
+
+/// for parsing commands that are not passed as cli args
+#[derive(Parser, Debug)]
+#[command(no_binary_name = true)]
+struct CommandLine {
+    #[command(subcommand)]
+    command: Command,
+}
+
+impl Command {
+    /// parses a json array of commands or a ';' separated list of commands
+    pub fn parse_batch(commands: &str) -> Result<Vec<Command>> {
+        let commands = commands.trim();
+        if commands.starts_with('[') {
+            return Ok(serde_json::from_str(commands)?);
+        }
+
+        split_commands(commands)?
+            .into_iter()
+            .map(|words| Ok(CommandLine::try_parse_from(words)?.command))
+            .collect()
+    }
+
+    /// parses a line of `hyprkool shell` input. a json object command, a json array of commands or
+    /// a ';' separated list of commands. multiple commands are run as a batch.
+    pub fn parse_line(line: &str) -> Result<Command> {
+        let line = line.trim();
+        if line.starts_with('{') {
+            return Ok(serde_json::from_str(line)?);
+        }
+
+        let mut commands = Self::parse_batch(line)?;
+        match commands.len() {
+            0 => Err(anyhow!("no command")),
+            1 => Ok(commands.remove(0)),
+            _ => Ok(Command::Batch {
+                commands: line.to_string(),
+                stop_on_error: false,
+            }),
+        }
+    }
+}
+
+/// splits a ';' separated list of commands into words (respecting '' and "" quotes)
+fn split_commands(s: &str) -> Result<Vec<Vec<String>>> {
+    let mut commands = vec![];
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote = None;
+
+    for c in s.chars() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => word.push(c),
+            None => match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    in_word = true;
+                }
+                ';' => {
+                    if in_word {
+                        words.push(std::mem::take(&mut word));
+                        in_word = false;
+                    }
+                    if !words.is_empty() {
+                        commands.push(std::mem::take(&mut words));
+                    }
+                }
+                c if c.is_whitespace() => {
+                    if in_word {
+                        words.push(std::mem::take(&mut word));
+                        in_word = false;
+                    }
+                }
+                c => {
+                    word.push(c);
+                    in_word = true;
+                }
+            },
+        }
+    }
+
+    if quote.is_some() {
+        return Err(anyhow!("unterminated quote in commands"));
+    }
+    if in_word {
+        words.push(word);
+    }
+    if !words.is_empty() {
+        commands.push(words);
+    }
+
+    Ok(commands)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_mouse_scroll_from_plugin() {
+        // see on_mouse_axis in plugin/src/main.cpp
+        assert_eq!(
+            Command::parse_line(r#"{"MouseScroll":{"x":-1,"y":0}}"#).unwrap(),
+            Command::MouseScroll { x: -1, y: 0 }
+        );
+    }
+
+    #[test]
+    fn formats_in_a_single_pass() {
+        let value = serde_json::json!([
+            {"workspace": "{activity}", "activity": "a", "named_focus": ["x", "y"]},
+            {"workspace": "2", "activity": null, "named_focus": []},
+        ]);
+        assert_eq!(
+            format_template("{workspace} {activity} {named_focus} {unknown} {", &value),
+            "{activity} a x,y {unknown} {\n2   {unknown} {"
+        );
+    }
 }
@@ -2,18 +2,23 @@ use std::collections::HashMap;
 use std::path::PathBuf;
 
 use anyhow::{anyhow, Result};
-use clap::{arg, command, Parser};
+use clap::Parser;
 use serde::Deserialize;
 
 use crate::command::Command;
+use crate::plugin::Animation;
 
 #[derive(Deserialize, Debug, Clone)]
 #[serde(default, deny_unknown_fields)]
 pub struct DaemonConfig {
     // TODO: maybe
     // pub enable: bool,
-    /// how long to wait for ipc responses before executing the command in ms
-    // pub ipc_timeout: u64,
+    /// how long to wait for each ipc response from the daemon (and the plugin) in ms
+    pub ipc_timeout: u64,
+    /// how many more times to try reaching the daemon before falling back to stateless commands
+    pub ipc_retries: u32,
+    /// how long the daemon waits for a client to send its handshake (or its next request) in ms
+    pub client_timeout: u64,
     pub fallback_commands: bool,
 
     /// remember what workspace was last focused on an activity
@@ -27,6 +32,7 @@ pub struct DaemonConfig {
     pub focus_last_window_on_monitor_change: bool,
 
     pub mouse: MouseConfig,
+    pub log: LogConfig,
 }
 impl Default for DaemonConfig {
     fn default() -> Self {
@@ -34,8 +40,47 @@ impl Default for DaemonConfig {
             focus_last_window_on_monitor_change: false,
             move_monitors_to_hyprkool_activity: true,
             remember_activity_focus: true,
+            ipc_timeout: 300,
+            ipc_retries: 2,
+            client_timeout: 1000,
             fallback_commands: true,
             mouse: Default::default(),
+            log: Default::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogTarget {
+    /// journald if the daemon runs under systemd, else stderr
+    Auto,
+    Stderr,
+    /// a file in `dir` that is rotated daily
+    File,
+    /// falls back to stderr if journald is not available
+    Journald,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    /// a level (error, warn, info, debug, trace) or filter directives like "hyprkool=debug,hyprland=warn".
+    /// $RUST_LOG takes precedence over this
+    pub level: String,
+    pub target: LogTarget,
+    /// directory for log files. $XDG_STATE_HOME/hyprkool if not set
+    pub dir: Option<PathBuf>,
+    /// number of rotated log files to keep
+    pub max_files: usize,
+}
+impl Default for LogConfig {
+    fn default() -> Self {
+        Self {
+            level: "info".into(),
+            target: LogTarget::Auto,
+            dir: None,
+            max_files: 7,
         }
     }
 }
@@ -50,6 +95,22 @@ pub struct MouseConfig {
     pub edge_width: u64,
     /// push cursor inside margin when it loops
     pub edge_margin: u64,
+    /// take the window being dragged along when switching workspaces on edge (needs the plugin)
+    pub move_dragged_window: bool,
+    /// suspend edge switching while a window of one of these classes is focused
+    pub inhibit_classes: Vec<String>,
+    /// suspend edge switching while a window with a title containing any of these is focused
+    pub inhibit_titles: Vec<String>,
+    /// only switch workspaces on edge while these modifiers are held. eg. "SUPER" or "SUPER SHIFT" (needs the plugin)
+    pub require_modifiers: Option<String>,
+    /// don't switch workspaces on edge while these modifiers are held (needs the plugin)
+    pub suppress_modifiers: Option<String>,
+    /// scrolling on left/right edges moves horizontally and on top/bottom edges moves vertically
+    /// (needs the plugin and the daemon. off while edge switching is disabled or inhibited)
+    pub scroll_on_edge: bool,
+    /// scrolling on an empty area of the desktop moves horizontally
+    /// (needs the plugin and the daemon. off while edge switching is disabled or inhibited)
+    pub scroll_on_empty_desktop: bool,
 }
 impl Default for MouseConfig {
     fn default() -> Self {
@@ -58,10 +119,135 @@ impl Default for MouseConfig {
             polling_rate: 300,
             edge_width: 0,
             edge_margin: 2,
+            move_dragged_window: true,
+            inhibit_classes: vec![],
+            inhibit_titles: vec![],
+            require_modifiers: None,
+            suppress_modifiers: None,
+            scroll_on_edge: false,
+            scroll_on_empty_desktop: false,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum AnimationMode {
+    /// use the plugin if it is loaded, else keyword
+    Auto,
+    Plugin,
+    /// set hyprland's workspace animation style using `hyprctl keyword` before each switch
+    Keyword,
+    /// leave workspace animations alone
+    Off,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TransitionAnimation {
+    /// slide in the direction of movement. activities slide horizontally (in the order of `activities`)
+    Slide,
+    /// same as slide, but horizontal movement slides vertically. (activities slide vertically)
+    SlideVert,
+    Fade,
+    None,
+}
+
+impl TransitionAnimation {
+    /// animation for a movement of (x, y)
+    pub fn animation(self, x: i32, y: i32) -> Animation {
+        match self {
+            Self::Slide => Animation::towards(x, y),
+            Self::SlideVert => {
+                if y == 0 {
+                    Animation::towards(0, x)
+                } else {
+                    Animation::towards(x, y)
+                }
+            }
+            Self::Fade => Animation::Fade,
+            Self::None => Animation::None,
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    GridMove,
+    ActivityChange,
+    MonitorChange,
+    HarpoonJump,
+    EdgeSwitch,
+}
+
+/// overrides for transitions into an activity
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActivityAnimations {
+    pub grid_move: Option<TransitionAnimation>,
+    pub activity_change: Option<TransitionAnimation>,
+    pub monitor_change: Option<TransitionAnimation>,
+    pub harpoon_jump: Option<TransitionAnimation>,
+    pub edge_switch: Option<TransitionAnimation>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnimationsConfig {
+    pub mode: AnimationMode,
+    /// in ms. hyprland's configured speed for workspace animations if not set
+    pub duration: Option<u32>,
+    /// name of a bezier curve defined in hyprland's config. hyprland's configured one if not set
+    pub curve: Option<String>,
+
+    /// moving around in the workspace grid
+    pub grid_move: TransitionAnimation,
+    pub activity_change: TransitionAnimation,
+    pub monitor_change: TransitionAnimation,
+    /// switching to a named focus
+    pub harpoon_jump: TransitionAnimation,
+    /// moving through screen edges with the mouse
+    pub edge_switch: TransitionAnimation,
+
+    /// (activity name -> overrides)
+    pub activities: HashMap<String, ActivityAnimations>,
+}
+impl Default for AnimationsConfig {
+    fn default() -> Self {
+        Self {
+            mode: AnimationMode::Auto,
+            duration: None,
+            curve: None,
+            grid_move: TransitionAnimation::Slide,
+            activity_change: TransitionAnimation::Fade,
+            monitor_change: TransitionAnimation::Fade,
+            harpoon_jump: TransitionAnimation::Fade,
+            edge_switch: TransitionAnimation::Slide,
+            activities: Default::default(),
+        }
+    }
+}
+impl AnimationsConfig {
+    /// animation for a transition into this activity
+    pub fn get(&self, transition: Transition, activity: Option<&str>) -> TransitionAnimation {
+        let overrides = activity.and_then(|a| self.activities.get(a));
+        let (default, overridden) = match transition {
+            Transition::GridMove => (self.grid_move, overrides.and_then(|o| o.grid_move)),
+            Transition::ActivityChange => (
+                self.activity_change,
+                overrides.and_then(|o| o.activity_change),
+            ),
+            Transition::MonitorChange => (
+                self.monitor_change,
+                overrides.and_then(|o| o.monitor_change),
+            ),
+            Transition::HarpoonJump => (self.harpoon_jump, overrides.and_then(|o| o.harpoon_jump)),
+            Transition::EdgeSwitch => (self.edge_switch, overrides.and_then(|o| o.edge_switch)),
+        };
+        overridden.unwrap_or(default)
+    }
+}
+
 #[derive(Deserialize, Debug, Clone)]
 #[serde(default, deny_unknown_fields)]
 pub struct Config {
@@ -70,6 +256,7 @@ pub struct Config {
     pub workspaces: (i32, i32),
     pub named_focii: HashMap<String, String>,
     pub daemon: DaemonConfig,
+    pub animations: AnimationsConfig,
 
     pub icon_theme: Option<String>,
     pub window_icon_try_min_size: Option<u16>,
@@ -81,6 +268,7 @@ impl Default for Config {
             workspaces: (2, 2),
             named_focii: Default::default(),
             daemon: Default::default(),
+            animations: Default::default(),
             icon_theme: None,
             window_icon_try_min_size: None,
         }
@@ -100,17 +288,31 @@ pub struct Cli {
     /// don't use daemon for this command even if one is active (mainly useful for debugging)
     #[arg(long)]
     pub force_no_daemon: bool,
+
+    /// hyprland instance to use. an instance signature, an index (see `hyprctl instances`) or 'auto'
+    /// 'auto' uses $HYPRLAND_INSTANCE_SIGNATURE if that instance is running, else the latest instance
+    #[arg(long)]
+    pub instance: Option<String>,
+
+    /// print the result of the command as a json object {ok, error, data}
+    #[arg(long, global = true)]
+    pub json: bool,
 }
 
 impl Cli {
-    pub fn config(&self) -> Result<Config> {
-        let config = self
-            .config_dir
+    /// None if there is no config file
+    pub fn config_path(&self) -> Option<PathBuf> {
+        self.config_dir
             .clone()
             .map(PathBuf::from)
             .or(dirs::config_dir().map(|pb| pb.join("hypr")))
             .map(|pb| pb.join("hyprkool.toml"))
             .filter(|p| p.exists())
+    }
+
+    pub fn config(&self) -> Result<Config> {
+        let config = self
+            .config_path()
             .map(std::fs::read_to_string)
             .transpose()?
             .map(|s| toml::from_str::<Config>(&s))
@@ -122,6 +324,37 @@ impl Cli {
             }
             _ => (),
         }
+        for mods in [
+            &config.daemon.mouse.require_modifiers,
+            &config.daemon.mouse.suppress_modifiers,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            parse_modifiers(mods)?;
+        }
         Ok(config)
     }
 }
+
+/// parses modifier names the way hyprland binds do. eg. "SUPER SHIFT" or "CTRL_ALT"
+pub fn parse_modifiers(mods: &str) -> Result<u32> {
+    let mut mask = 0;
+    for m in mods
+        .split(|c: char| c.is_whitespace() || c == '_' || c == '+')
+        .filter(|m| !m.is_empty())
+    {
+        mask |= match m.to_uppercase().as_str() {
+            "SHIFT" => 1 << 0,
+            "CAPS" => 1 << 1,
+            "CTRL" | "CONTROL" => 1 << 2,
+            "ALT" | "MOD1" => 1 << 3,
+            "MOD2" => 1 << 4,
+            "MOD3" => 1 << 5,
+            "SUPER" | "WIN" | "LOGO" | "MOD4" | "META" => 1 << 6,
+            "MOD5" => 1 << 7,
+            _ => return Err(anyhow!("unknown modifier '{}'", m)),
+        };
+    }
+    Ok(mask)
+}
@@ -0,0 +1,62 @@
+use serde::{Deserialize, Serialize};
+
+/// kinds of errors that scripts might want to tell apart. decides the exit code of the cli.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    Other,
+    /// could not connect to the daemon, or it did not reply
+    DaemonUnreachable,
+    /// the command could not be executed
+    Rejected,
+    /// the command needs a hyprkool workspace to be focused
+    NotInKoolWorkspace,
+}
+
+impl ErrorKind {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            Self::Other => 1,
+            Self::DaemonUnreachable => 2,
+            Self::Rejected => 3,
+            Self::NotInKoolWorkspace => 4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KoolError {
+    pub kind: ErrorKind,
+    pub message: String,
+}
+
+impl KoolError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self {
+            kind,
+            message: message.into(),
+        }
+    }
+
+    pub fn not_in_kool_workspace() -> Self {
+        Self::new(ErrorKind::NotInKoolWorkspace, "not in a hyprkool workspace")
+    }
+
+    /// keeps the kind if there is a [`KoolError`] somewhere in the chain
+    pub fn from_anyhow(e: &anyhow::Error, default: ErrorKind) -> Self {
+        let kind = e
+            .chain()
+            .find_map(|e| e.downcast_ref::<KoolError>())
+            .map(|e| e.kind)
+            .unwrap_or(default);
+        Self::new(kind, format!("{}", e))
+    }
+}
+
+impl std::fmt::Display for KoolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for KoolError {}
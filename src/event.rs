@@ -1,31 +1,69 @@
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::{anyhow, Context, Result};
 use hyprland::event_listener::AsyncEventListener;
 use hyprland::shared::WorkspaceType;
 use serde::{Deserialize, Serialize};
-use tokio::io::BufWriter;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::unix::OwnedReadHalf;
+use tokio::net::unix::OwnedWriteHalf;
 use tokio::net::UnixListener;
 use tokio::net::UnixStream;
+use tokio::signal::unix::{signal, SignalKind};
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
+use tokio::sync::oneshot;
 use tokio::sync::Mutex;
+use tracing::{debug, error, info, info_span, warn, Instrument};
 
 use crate::command::Command;
+use crate::command::InfoCommand;
+use crate::command::InfoOutputStream;
 use crate::config::Config;
+use crate::config::DaemonConfig;
+use crate::error::ErrorKind;
+use crate::error::KoolError;
+use crate::hyprctl::instance_alive;
+use crate::hyprctl::runtime_dir;
+use crate::info::CommandResult;
 use crate::info::InfoCommandContext;
 use crate::info::KInfoEvent;
+use crate::info::KoolEvent;
 use crate::state::State;
+use crate::status::DaemonStatus;
+use crate::status::ErrorLog;
+use crate::status::Status;
+use crate::systemd;
 
+/// bump this whenever the messages exchanged between cli and daemon change
+/// (keep in sync with KOOL_PROTOCOL_VERSION in plugin/src/main.cpp)
+pub const PROTOCOL_VERSION: u32 = 3;
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// NOTE: variant names are part of the protocol
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub enum Message {
+    Hello {
+        protocol: u32,
+        version: String,
+    },
     IpcOk,
     IpcErr(String),
+    /// plain text (like `query --format` output)
     IpcMessage(String),
+    /// json data returned by a command
+    IpcData(serde_json::Value),
     Command(Command),
+    /// a command failed
+    Error(KoolError),
 }
 impl Message {
     pub fn msg(&self) -> Vec<u8> {
@@ -33,6 +71,262 @@ impl Message {
         bytes.extend_from_slice(b"\n");
         bytes
     }
+
+    /// reply to a command that returned this
+    pub fn reply(data: Option<serde_json::Value>) -> Self {
+        match data {
+            None => Self::IpcOk,
+            Some(serde_json::Value::String(s)) => Self::IpcMessage(s),
+            Some(v) => Self::IpcData(v),
+        }
+    }
+
+    pub fn hello() -> Self {
+        Self::Hello {
+            protocol: PROTOCOL_VERSION,
+            version: VERSION.to_string(),
+        }
+    }
+}
+
+/// every line sent over the hyprkool socket is a packet.
+/// the first packet on a connection is always a [`Message::Hello`] handshake (with id 0).
+/// replies carry the id of the request they answer.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub id: u64,
+    pub message: Message,
+}
+impl Packet {
+    pub fn msg(&self) -> Vec<u8> {
+        let mut bytes = serde_json::to_string(self).unwrap().into_bytes();
+        bytes.extend_from_slice(b"\n");
+        bytes
+    }
+}
+
+/// connects to the daemon and performs the handshake, retrying on timeouts.
+/// returns None if the daemon could not be reached.
+pub async fn connect_daemon(config: &DaemonConfig) -> Result<Option<KoolClient>> {
+    let sock_path = get_socket_path()?;
+    let timeout = Duration::from_millis(config.ipc_timeout);
+
+    // eg. the daemon is busy starting up, or restarting
+    const RETRY_DELAY: Duration = Duration::from_millis(100);
+
+    for attempt in 0..=config.ipc_retries {
+        if attempt > 0 {
+            tokio::time::sleep(RETRY_DELAY).await;
+        }
+        let sock = match UnixStream::connect(&sock_path).await {
+            Ok(sock) => sock,
+            Err(e) => match e.kind() {
+                // nobody is listening
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::ConnectionRefused => {
+                    return Ok(None);
+                }
+                _ => {
+                    warn!("could not connect to hyprkool daemon: {}", e);
+                    continue;
+                }
+            },
+        };
+        match tokio::time::timeout(timeout, KoolClient::handshake(sock)).await {
+            Ok(client) => return client.map(Some),
+            Err(_) => {
+                warn!("timeout. could not connect to hyprkool daemon");
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn version_mismatch_err(daemon: (&str, u32), cli: (&str, u32)) -> String {
+    format!(
+        "daemon is version {} (protocol {}), cli is version {} (protocol {}). restart the daemon",
+        daemon.0, daemon.1, cli.0, cli.1
+    )
+}
+
+/// cli side of a connection to the daemon
+pub struct KoolClient {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+}
+
+impl KoolClient {
+    /// performs the version handshake with the daemon
+    pub async fn handshake(sock: UnixStream) -> Result<Self> {
+        let (reader, writer) = sock.into_split();
+        let mut client = Self {
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 1,
+        };
+
+        client
+            .write(&Packet {
+                id: 0,
+                message: Message::hello(),
+            })
+            .await?;
+
+        let Some(packet) = client.recv().await.ok().flatten() else {
+            // daemons from before the handshake existed just drop the connection
+            return Err(anyhow!(
+                "daemon did not answer the handshake. it is probably an older version than the cli ({}). restart the daemon",
+                VERSION
+            ));
+        };
+        match packet.message {
+            Message::Hello { protocol, .. } if protocol == PROTOCOL_VERSION => Ok(client),
+            Message::Hello { protocol, version } => Err(anyhow!(version_mismatch_err(
+                (&version, protocol),
+                (VERSION, PROTOCOL_VERSION)
+            ))),
+            Message::IpcErr(e) => Err(anyhow!(e)),
+            m => Err(anyhow!("unexpected handshake reply from daemon: {:?}", m)),
+        }
+    }
+
+    async fn write(&mut self, packet: &Packet) -> Result<()> {
+        self.writer.write_all(&packet.msg()).await?;
+        self.writer.flush().await?;
+        Ok(())
+    }
+
+    /// returns the id of the request
+    pub async fn send(&mut self, message: Message) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.write(&Packet { id, message }).await?;
+        Ok(id)
+    }
+
+    /// tell the daemon that no more requests will be sent on this connection
+    pub async fn finish(&mut self) -> Result<()> {
+        self.writer.shutdown().await?;
+        Ok(())
+    }
+
+    /// returns None if the daemon closed the connection
+    pub async fn recv(&mut self) -> Result<Option<Packet>> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            return Ok(None);
+        }
+        let packet = serde_json::from_str(&line)
+            .with_context(|| format!("unexpected message from daemon: {}", line.trim()))?;
+        Ok(Some(packet))
+    }
+
+    /// waits for the reply to the request with this id
+    pub async fn recv_reply(&mut self, id: u64) -> Result<Message> {
+        loop {
+            match self.recv().await? {
+                Some(packet) if packet.id == id => return Ok(packet.message),
+                Some(_) => continue,
+                None => return Err(anyhow!("daemon closed the connection without replying")),
+            }
+        }
+    }
+
+    /// sends a command and waits for its reply.
+    /// the outer error is for a broken connection, the inner one is the daemon's error for the command.
+    pub async fn execute(
+        &mut self,
+        command: Command,
+        timeout: Duration,
+    ) -> Result<std::result::Result<Option<serde_json::Value>, KoolError>> {
+        let id = self.send(Message::Command(command)).await?;
+        let reply = tokio::time::timeout(timeout, self.recv_reply(id))
+            .await
+            .map_err(|_| anyhow!("timeout. hyprkool daemon did not reply in time"))??;
+        match reply {
+            Message::IpcOk => Ok(Ok(None)),
+            Message::IpcMessage(message) => Ok(Ok(Some(serde_json::Value::String(message)))),
+            Message::IpcData(data) => Ok(Ok(Some(data))),
+            Message::Error(e) => Ok(Err(e)),
+            Message::IpcErr(message) => Ok(Err(KoolError::new(ErrorKind::Rejected, message))),
+            m => Err(anyhow!("unexpected reply from daemon: {:?}", m)),
+        }
+    }
+}
+
+/// runs commands read from stdin (one per line) over a single connection to the daemon.
+/// prints one json [`CommandResult`] per line.
+pub async fn shell(config: Config) -> Result<()> {
+    let timeout = Duration::from_millis(config.daemon.ipc_timeout);
+    // the daemon closes connections that have been quiet for client_timeout.
+    // reconnect well before that, so that a request never races the daemon closing it.
+    let idle_timeout = Duration::from_millis(config.daemon.client_timeout / 2);
+    let mut client = connect_daemon(&config.daemon).await?;
+    let mut last_request = Instant::now();
+    let mut state = None;
+
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let command = match Command::parse_line(line) {
+            Ok(Command::Daemon | Command::Info { .. } | Command::Shell) => {
+                Err(anyhow!("this command can't be used in the shell"))
+            }
+            res => res,
+        };
+        let res = match command {
+            Ok(command) => {
+                if last_request.elapsed() >= idle_timeout {
+                    if let Some(mut c) = client.take() {
+                        _ = c.finish().await;
+                    }
+                }
+                if client.is_none() {
+                    // the daemon might have been (re)started
+                    client = connect_daemon(&config.daemon).await?;
+                }
+                last_request = Instant::now();
+                match &mut client {
+                    Some(c) => match c.execute(command, timeout).await {
+                        Ok(res) => res.map_err(anyhow::Error::from),
+                        Err(e) => {
+                            // can't trust this connection anymore. try again on the next line
+                            client = None;
+                            Err(e)
+                        }
+                    },
+                    None if config.daemon.fallback_commands => {
+                        if state.is_none() {
+                            state = Some(State::new(config.clone()).await?);
+                        }
+                        let state = state.as_mut().expect("just set");
+                        match state.update_monitors().await {
+                            Ok(()) => state.execute(command, None).await,
+                            Err(e) => Err(e),
+                        }
+                    }
+                    None => Err(anyhow!("could not connect to hyprkool daemon")),
+                }
+            }
+            Err(e) => Err(e),
+        };
+
+        let mut out = serde_json::to_vec(&CommandResult::from(res))?;
+        out.push(b'\n');
+        stdout.write_all(&out).await?;
+        stdout.flush().await?;
+    }
+
+    if let Some(mut c) = client {
+        c.finish().await?;
+    }
+    Ok(())
 }
 
 #[derive(Clone, Debug)]
@@ -55,35 +349,98 @@ pub enum KEvent {
     Submap {
         name: String,
     },
+    ConfigReloaded,
 
     MonitorInfoRequested,
+    EdgeSwitchInfoRequested,
+    SubmapInfoRequested,
+
+    /// connection to hyprland's event socket was re-established. events might have been missed.
+    HyprlandReconnected,
+
+    /// events that the daemon itself produces
+    Kool(KoolEvent),
+}
+
+/// requests from ipc connections for the task that owns the daemon's state
+enum DaemonRequest {
+    Command {
+        command: Command,
+        /// logs while executing the command belong to the connection that sent it
+        span: tracing::Span,
+        reply: oneshot::Sender<Result<Option<serde_json::Value>>>,
+    },
+    Quit,
 }
 
 struct KEventListener {
     sock: UnixListener,
-    hl_events: AsyncEventListener,
 
     event_tx: mpsc::Sender<KEvent>,
     event_rx: mpsc::Receiver<KEvent>,
 
+    request_tx: mpsc::Sender<DaemonRequest>,
+    request_rx: mpsc::Receiver<DaemonRequest>,
+
     info_event_tx: broadcast::Sender<KInfoEvent>,
     info_event_rx: broadcast::Receiver<KInfoEvent>,
 }
 
 impl KEventListener {
-    pub async fn new() -> Result<Self> {
+    /// `systemd_sock` is used instead of creating a socket if it's passed
+    pub async fn new(
+        ipc_timeout: Duration,
+        systemd_sock: Option<std::os::unix::net::UnixListener>,
+    ) -> Result<Self> {
         let (hl_tx, hl_rx) = mpsc::channel(100);
         let (info_tx, info_rx) = broadcast::channel(100);
+        let (request_tx, request_rx) = mpsc::channel(100);
         Ok(Self {
-            sock: Self::ipc_sock().await?,
-            hl_events: Self::hl_event_listener(hl_tx.clone())?,
+            sock: Self::ipc_sock(ipc_timeout, systemd_sock).await?,
             event_tx: hl_tx,
             event_rx: hl_rx,
+            request_tx,
+            request_rx,
             info_event_tx: info_tx,
             info_event_rx: info_rx,
         })
     }
 
+    /// listens to hyprland events. reconnects (with backoff) if hyprland's event socket goes away
+    /// (it can briefly drop on config reloads). only returns once the hyprland instance is gone.
+    async fn hl_listen(tx: mpsc::Sender<KEvent>) -> Result<()> {
+        const MIN_BACKOFF: Duration = Duration::from_millis(50);
+        const MAX_BACKOFF: Duration = Duration::from_secs(5);
+
+        let mut backoff = MIN_BACKOFF;
+        let mut reconnecting = false;
+        loop {
+            let mut el = Self::hl_event_listener(tx.clone())?;
+            if reconnecting {
+                _ = tx.send(KEvent::HyprlandReconnected).await;
+            }
+
+            let start = std::time::Instant::now();
+            match el.start_listener_async().await {
+                Ok(()) => warn!("hyprland event socket closed"),
+                Err(e) => warn!("hyprland event socket error: {:?}", e),
+            }
+
+            if !instance_alive() {
+                return Err(anyhow!("hyprland instance is gone"));
+            }
+
+            // the connection was healthy for a while. this is a fresh disconnect
+            if start.elapsed() > MAX_BACKOFF {
+                backoff = MIN_BACKOFF;
+            }
+            info!("reconnecting to hyprland event socket in {:?}", backoff);
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(MAX_BACKOFF);
+            reconnecting = true;
+        }
+    }
+
     fn hl_event_listener(_tx: mpsc::Sender<KEvent>) -> Result<AsyncEventListener> {
         let mut el = AsyncEventListener::new();
         let tx = _tx.clone();
@@ -141,6 +498,13 @@ impl KEventListener {
             })
         });
         let tx = _tx.clone();
+        el.add_config_reloaded_handler(move || {
+            let tx = tx.clone();
+            Box::pin(async move {
+                _ = tx.send(KEvent::ConfigReloaded).await;
+            })
+        });
+        let tx = _tx.clone();
         el.add_monitor_added_handler(move |m| {
             let tx = tx.clone();
             Box::pin(async move {
@@ -157,35 +521,27 @@ impl KEventListener {
         Ok(el)
     }
 
-    async fn ipc_sock() -> Result<UnixListener> {
+    async fn ipc_sock(
+        timeout: Duration,
+        systemd_sock: Option<std::os::unix::net::UnixListener>,
+    ) -> Result<UnixListener> {
+        // systemd owns the socket. if a daemon was running, systemd would not have started us
+        if let Some(sock) = systemd_sock {
+            info!("using socket from systemd socket activation");
+            sock.set_nonblocking(true)?;
+            return Ok(UnixListener::from_std(sock)?);
+        }
+
         let sock_path = get_socket_path()?;
 
         // - [Unix sockets, the basics in Rust - Emmanuel Bosquet](https://emmanuelbosquet.com/2022/whatsaunixsocket/)
         // send a quit message to any daemon that might be running. ignore all errors
         if let Ok(sock) = UnixStream::connect(&sock_path).await {
-            let mut sock = BufWriter::new(sock);
-            let _ = sock
-                .write_all(&Message::Command(Command::DaemonQuit).msg())
-                .await;
-            let _ = sock.flush().await;
-            let _ = sock.shutdown().await;
-
-            let sleep = tokio::time::sleep(Duration::from_millis(300));
-            let mut sock = BufReader::new(sock);
-            let mut line = String::new();
+            let sleep = tokio::time::sleep(timeout);
             tokio::select! {
-                res = sock.read_line(&mut line) => {
-                    let _ = res;
-                    if let Ok(command) = serde_json::from_str(&line) {
-                        match command {
-                            Message::IpcOk => { }
-                            Message::IpcErr(message) => {
-                                println!("{}", message);
-                            }
-                            _ => {
-                                unreachable!();
-                            }
-                        }
+                res = Self::quit_daemon(sock) => {
+                    if let Err(e) = res {
+                        warn!("could not quit the running daemon: {}", e);
                     }
                 }
                 _ = sleep => { }
@@ -201,69 +557,201 @@ impl KEventListener {
         Ok(sock)
     }
 
+    async fn quit_daemon(sock: UnixStream) -> Result<()> {
+        match KoolClient::handshake(sock).await {
+            Ok(mut client) => {
+                let id = client.send(Message::Command(Command::DaemonQuit)).await?;
+                client.finish().await?;
+                match client.recv_reply(id).await? {
+                    Message::IpcOk => {}
+                    Message::IpcErr(message) => {
+                        warn!("{}", message);
+                    }
+                    m => {
+                        warn!("unexpected reply from daemon: {:?}", m);
+                    }
+                }
+            }
+            Err(_) => {
+                // daemons from older versions don't know about packets
+                let sock_path = get_socket_path()?;
+                let mut sock = UnixStream::connect(&sock_path).await?;
+                sock.write_all(&Message::Command(Command::DaemonQuit).msg())
+                    .await?;
+                sock.flush().await?;
+                sock.shutdown().await?;
+                let mut line = String::new();
+                let _ = BufReader::new(sock).read_line(&mut line).await;
+            }
+        }
+        Ok(())
+    }
+
+    /// runs in its own task for every connection. commands are executed by the task that owns
+    /// the state (see [`DaemonRequest`]), so a slow client can't block anyone else.
     async fn process_ipc_conn(
         stream: UnixStream,
-        state: &mut State,
-        kevent_tx: &mpsc::Sender<KEvent>,
-        kinfo_event_tx: &broadcast::Sender<KInfoEvent>,
+        read_timeout: Duration,
+        request_tx: mpsc::Sender<DaemonRequest>,
+        kevent_tx: mpsc::Sender<KEvent>,
+        kinfo_event_tx: broadcast::Sender<KInfoEvent>,
         info_ctx: Arc<Mutex<InfoCommandContext>>,
-    ) -> Result<bool> {
-        let mut sock = BufReader::new(stream);
+    ) -> Result<()> {
+        check_peer(&stream)?;
+
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
         let mut line = String::new();
-        sock.read_line(&mut line).await?;
+        tokio::time::timeout(read_timeout, reader.read_line(&mut line))
+            .await
+            .context("client did not send a handshake in time")??;
         if line.is_empty() {
-            return Ok(false);
+            return Ok(());
         }
-        let message = serde_json::from_str::<Message>(&line)?;
-        match message {
-            Message::Command(Command::DaemonQuit) => {
-                sock.write_all(&Message::IpcOk.msg()).await?;
-                sock.flush().await?;
-                return Ok(true);
+        match serde_json::from_str::<Packet>(&line) {
+            Ok(Packet {
+                id,
+                message: Message::Hello { protocol, version },
+            }) => {
+                let message = if protocol == PROTOCOL_VERSION {
+                    Message::hello()
+                } else {
+                    Message::IpcErr(version_mismatch_err(
+                        (VERSION, PROTOCOL_VERSION),
+                        (&version, protocol),
+                    ))
+                };
+                writer.write_all(&Packet { id, message }.msg()).await?;
+                writer.flush().await?;
+                if protocol != PROTOCOL_VERSION {
+                    return Ok(());
+                }
             }
-            Message::Command(Command::Info { command, monitor }) => {
-                let tx = kevent_tx.clone();
-                let rx = kinfo_event_tx.subscribe();
-
-                #[allow(clippy::let_underscore_future)]
-                tokio::spawn(async move {
-                    match command
-                        .listen_loop(sock.into_inner(), tx, rx, monitor, info_ctx)
-                        .await
-                    {
-                        Ok(()) => {}
-                        Err(_e) => {
-                            // NOTE: we ignore these errors, as the only errors can be when socket is broken
-                            // println!("error in info command: {:?}", _e);
-                        }
-                    }
-                });
+            Ok(Packet { id, message }) => {
+                let message = Message::IpcErr(format!("expected a handshake. got: {:?}", message));
+                writer.write_all(&Packet { id, message }.msg()).await?;
+                writer.flush().await?;
+                return Ok(());
             }
-            Message::Command(command) => {
-                match state.execute(command, Some(kevent_tx.clone())).await {
-                    Ok(_) => {
-                        sock.write_all(&Message::IpcOk.msg()).await?;
-                    }
-                    Err(e) => {
-                        println!("error when executing command: {:?}", e);
-                        sock.write_all(&Message::IpcErr(format!("error: {}", e)).msg())
-                            .await?;
-                    }
+            Err(_) => {
+                // clis from older versions send bare messages and understand bare messages
+                let message = Message::IpcErr(format!(
+                    "daemon is version {}, cli is an older version. restart the daemon",
+                    VERSION
+                ));
+                writer.write_all(&message.msg()).await?;
+                writer.flush().await?;
+                return Ok(());
+            }
+        }
+
+        // every request has the same deadline as the handshake. clients that keep the connection
+        // open (like `hyprkool shell`) reconnect once they have been quiet for that long.
+        loop {
+            let mut line = String::new();
+            match tokio::time::timeout(read_timeout, reader.read_line(&mut line)).await {
+                Ok(res) => {
+                    res?;
+                }
+                Err(_) => {
+                    debug!("closing idle connection");
+                    return Ok(());
                 }
-                sock.flush().await?;
             }
-            _ => {
-                unreachable!();
+            if line.is_empty() {
+                return Ok(());
             }
-        }
+            let Packet { id, message } = match serde_json::from_str::<Packet>(&line) {
+                Ok(p) => p,
+                Err(e) => {
+                    let message = Message::IpcErr(format!("could not parse request: {}", e));
+                    writer.write_all(&Packet { id: 0, message }.msg()).await?;
+                    writer.flush().await?;
+                    continue;
+                }
+            };
+
+            match message {
+                Message::Command(Command::DaemonQuit) => {
+                    writer
+                        .write_all(
+                            &Packet {
+                                id,
+                                message: Message::IpcOk,
+                            }
+                            .msg(),
+                        )
+                        .await?;
+                    writer.flush().await?;
+                    request_tx.send(DaemonRequest::Quit).await?;
+                    return Ok(());
+                }
+                Message::Command(Command::Info { command, monitor }) => {
+                    let tx = kevent_tx;
+                    let rx = kinfo_event_tx.subscribe();
 
-        Ok(false)
+                    // info commands take over the rest of the connection
+                    let out = InfoOutputStream::Sock { sock: writer, id };
+                    let span = info_span!("info", id, command = ?command);
+                    #[allow(clippy::let_underscore_future)]
+                    tokio::spawn(
+                        async move {
+                            debug!("listening");
+                            match command.listen_loop(out, tx, rx, monitor, info_ctx).await {
+                                Ok(()) => {}
+                                Err(e) => {
+                                    // NOTE: the only errors can be when socket is broken
+                                    debug!("info client went away: {:?}", e);
+                                }
+                            }
+                        }
+                        .instrument(span),
+                    );
+                    return Ok(());
+                }
+                Message::Command(command) => {
+                    let span = info_span!("ipc", id, command = ?command);
+                    let (reply_tx, reply_rx) = oneshot::channel();
+                    request_tx
+                        .send(DaemonRequest::Command {
+                            command,
+                            span,
+                            reply: reply_tx,
+                        })
+                        .await?;
+                    let message = match reply_rx.await? {
+                        Ok(data) => Message::reply(data),
+                        Err(e) => Message::Error(KoolError::from_anyhow(&e, ErrorKind::Rejected)),
+                    };
+                    writer.write_all(&Packet { id, message }.msg()).await?;
+                }
+                message => {
+                    let message = Message::IpcErr(format!("unexpected message: {:?}", message));
+                    writer.write_all(&Packet { id, message }.msg()).await?;
+                }
+            }
+            writer.flush().await?;
+        }
     }
 }
 
-pub async fn daemon(config: Config) -> Result<()> {
+/// `errors` should be the same [`ErrorLog`] that logging was set up with.
+/// `systemd_sock` is the socket from systemd socket activation (see [`systemd::listen_fds`])
+pub async fn daemon(
+    config: Config,
+    config_path: Option<PathBuf>,
+    errors: ErrorLog,
+    systemd_sock: Option<std::os::unix::net::UnixListener>,
+) -> Result<()> {
+    let started = Instant::now();
+    systemd::status("starting");
     let mut state = State::new(config.clone()).await?;
-    let mut el = KEventListener::new().await?;
+    let mut el = KEventListener::new(
+        Duration::from_millis(config.daemon.ipc_timeout),
+        systemd_sock,
+    )
+    .await?;
     let info_ctx = InfoCommandContext {
         config: config.clone(),
         icons: Default::default(),
@@ -271,8 +759,10 @@ pub async fn daemon(config: Config) -> Result<()> {
     let info_ctx = Arc::new(Mutex::new(info_ctx));
 
     let sleep_duration = std::time::Duration::from_millis(config.daemon.mouse.polling_rate);
+    let read_timeout = Duration::from_millis(config.daemon.client_timeout);
 
     if config.daemon.move_monitors_to_hyprkool_activity {
+        systemd::status("moving monitors to hyprkool activities");
         for name in state
             .monitors
             .iter()
@@ -285,33 +775,166 @@ pub async fn daemon(config: Config) -> Result<()> {
         }
     }
 
-    let mut hl_fut = std::pin::pin!(el.hl_events.start_listener_async());
+    systemd::ready("running");
+    info!("daemon ready");
+
+    let mut hl_fut = std::pin::pin!(KEventListener::hl_listen(el.event_tx.clone()));
     let mut tick_fut = std::pin::pin!(tokio::time::sleep(sleep_duration));
+    let mut sigterm = signal(SignalKind::terminate())?;
+    let mut sigint = signal(SignalKind::interrupt())?;
+
+    let res: Result<()> = async {
+        loop {
+            tokio::select! {
+                _ = sigterm.recv() => {
+                    systemd::stopping();
+                    break Ok(());
+                }
+                _ = sigint.recv() => {
+                    systemd::stopping();
+                    break Ok(());
+                }
+                event = hl_fut.as_mut() => {
+                    event?;
+                    return Err(anyhow!("hyprland event listener exited"));
+                }
+                event = el.info_event_rx.recv() => {
+                    match event {
+                        Ok(e) => debug!("info event: {:?}", e),
+                        Err(broadcast::error::RecvError::Lagged(_)) => { },
+                        Err(broadcast::error::RecvError::Closed) => {
+                            return Err(anyhow!("info event channel closed"));
+                        },
+                    }
+                }
+                event = el.event_rx.recv() => {
+                    match event {
+                        Some(event) => {
+                            match state.update(event, el.info_event_tx.clone()).await {
+                                Ok(()) => {},
+                                Err(e) => error!("error during updating state: {:?}", e),
+                            }
+                        },
+                        None => {
+                            return Err(anyhow!("hl event channel closed"));
+                        }
+                    }
+                }
+                event = el.sock.accept() => {
+                    match event {
+                        Ok((stream, _addr)) => {
+                            let conn = KEventListener::process_ipc_conn(
+                                stream,
+                                read_timeout,
+                                el.request_tx.clone(),
+                                el.event_tx.clone(),
+                                el.info_event_tx.clone(),
+                                info_ctx.clone(),
+                            );
+                            #[allow(clippy::let_underscore_future)]
+                            tokio::spawn(async move {
+                                if let Err(e) = conn.await {
+                                    error!("error during ipc connection: {:?}", e);
+                                }
+                            });
+                        },
+                        Err(e) => error!("hyprkool socket conn error: {:?}", e),
+                    }
+                }
+                request = el.request_rx.recv() => {
+                    match request {
+                        Some(DaemonRequest::Command { command: Command::Status, span, reply }) => {
+                            // the daemon's own receiver is not a client
+                            let info_clients = el.info_event_tx.receiver_count().saturating_sub(1);
+                            let daemon = DaemonStatus::new(started, config_path.clone(), info_clients, &errors);
+                            let timeout = Duration::from_millis(state.config.daemon.ipc_timeout);
+                            let status = Status::new(Some(daemon), &mut state.plugin, timeout).instrument(span).await;
+                            _ = reply.send(serde_json::to_value(status).map(Some).map_err(Into::into));
+                        },
+                        Some(DaemonRequest::Command { command, span, reply }) => {
+                            let tx = Some(el.event_tx.clone());
+                            let res = async {
+                                debug!("executing");
+                                let res = state.execute(command, tx).await;
+                                if let Err(e) = &res {
+                                    // user mistakes should not drown out the errors in `hyprkool status`
+                                    match KoolError::from_anyhow(e, ErrorKind::Rejected).kind {
+                                        ErrorKind::NotInKoolWorkspace => debug!("command failed: {:?}", e),
+                                        ErrorKind::Rejected => warn!("command failed: {:?}", e),
+                                        _ => error!("error when executing command: {:?}", e),
+                                    }
+                                }
+                                res
+                            }
+                            .instrument(span)
+                            .await;
+                            // the client might have gone away. nothing to do then
+                            _ = reply.send(res);
+                        },
+                        Some(DaemonRequest::Quit) => {
+                            systemd::stopping();
+                            break Ok(());
+                        },
+                        None => {
+                            return Err(anyhow!("ipc request channel closed"));
+                        }
+                    }
+                }
+                _  = tick_fut.as_mut() => {
+                    tick_fut.as_mut().set(tokio::time::sleep(sleep_duration));
+
+                    match state.tick(el.info_event_tx.clone()).await {
+                        Ok(()) => {},
+                        Err(e) => error!("hyprkool errored while ticking: {:?}", e),
+                    }
+                }
+            }
+        }
+    }
+    .await;
+
+    state.restore().await;
+    res
+}
+
+/// answers info commands using an in-process event loop when the daemon is not running
+pub async fn info_without_daemon(
+    config: Config,
+    command: InfoCommand,
+    monitor: bool,
+) -> Result<()> {
+    let mut state = State::new(config.clone()).await?;
+    let (event_tx, mut event_rx) = mpsc::channel(100);
+    let (info_event_tx, _info_event_rx) = broadcast::channel(100);
+    let info_ctx = Arc::new(Mutex::new(InfoCommandContext {
+        config,
+        icons: Default::default(),
+    }));
+
+    let mut hl_fut = std::pin::pin!(KEventListener::hl_listen(event_tx.clone()));
+    let mut listen_fut = std::pin::pin!(command.listen_loop(
+        InfoOutputStream::Stdout,
+        event_tx,
+        info_event_tx.subscribe(),
+        monitor,
+        info_ctx,
+    ));
 
     loop {
         tokio::select! {
+            res = listen_fut.as_mut() => {
+                return res;
+            }
             event = hl_fut.as_mut() => {
                 event?;
-                return Err(anyhow!("Hyprland socket closed?"));
-            }
-            event = el.info_event_rx.recv() => {
-                match event {
-                    Ok(_e) => {
-                        // nothing to do here
-                        // dbg!(_e);
-                    },
-                    Err(broadcast::error::RecvError::Lagged(_)) => { },
-                    Err(broadcast::error::RecvError::Closed) => {
-                        return Err(anyhow!("info event channel closed"));
-                    },
-                }
+                return Err(anyhow!("hyprland event listener exited"));
             }
-            event = el.event_rx.recv() => {
+            event = event_rx.recv() => {
                 match event {
                     Some(event) => {
-                        match state.update(event, el.info_event_tx.clone()).await {
+                        match state.update(event, info_event_tx.clone()).await {
                             Ok(()) => {},
-                            Err(e) => println!("error during updating state: {:?}", e),
+                            Err(e) => error!("error during updating state: {:?}", e),
                         }
                     },
                     None => {
@@ -319,29 +942,6 @@ pub async fn daemon(config: Config) -> Result<()> {
                     }
                 }
             }
-            event = el.sock.accept() => {
-                match event {
-                    Ok((stream, _addr)) => {
-                        match KEventListener::process_ipc_conn(stream, &mut state, &el.event_tx, &el.info_event_tx, info_ctx.clone()).await {
-                            Ok(quit) => {
-                                if quit {
-                                    break Ok(());
-                                }
-                            },
-                            Err(e) => println!("error during ipc connection: {:?}", e),
-                        }
-                    },
-                    Err(e) =>  println!("hyprkool socket conn error: {:?}", e),
-                }
-            }
-            _  = tick_fut.as_mut() => {
-                tick_fut.as_mut().set(tokio::time::sleep(sleep_duration));
-
-                match state.tick().await {
-                    Ok(()) => {},
-                    Err(e) =>  println!("hyprkool errored while ticking: {:?}", e),
-                }
-            }
         }
     }
 }
@@ -349,11 +949,27 @@ pub async fn daemon(config: Config) -> Result<()> {
 pub fn get_socket_dir() -> Result<PathBuf> {
     let hypr_signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
         .context("could not get HYPRLAND_INSTANCE_SIGNATURE")?;
-    let mut sock_path = PathBuf::from("/tmp/hyprkool");
+    let mut sock_path = runtime_dir();
+    sock_path.push("hyprkool");
     sock_path.push(&hypr_signature);
     if std::fs::metadata(&sock_path).is_err() {
-        std::fs::create_dir_all(&sock_path)?;
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&sock_path)?;
     }
+    // fails if someone else owns this dir
+    std::fs::set_permissions(&sock_path, std::fs::Permissions::from_mode(0o700))
+        .with_context(|| format!("could not restrict permissions of {:?}", &sock_path))?;
+    Ok(sock_path)
+}
+
+/// where older plugin builds put their socket
+fn get_legacy_socket_dir() -> Result<PathBuf> {
+    let hypr_signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .context("could not get HYPRLAND_INSTANCE_SIGNATURE")?;
+    let mut sock_path = PathBuf::from("/tmp/hyprkool");
+    sock_path.push(&hypr_signature);
     Ok(sock_path)
 }
 
@@ -365,76 +981,112 @@ pub fn get_socket_path() -> Result<PathBuf> {
 pub fn get_plugin_socket_path() -> Result<PathBuf> {
     let mut sock_path = get_socket_dir()?;
     sock_path.push("plugin.sock");
+    if sock_path.exists() {
+        return Ok(sock_path);
+    }
+
+    let legacy_dir = get_legacy_socket_dir()?;
+    if let Some(legacy_path) = legacy_plugin_socket(&legacy_dir) {
+        return Ok(legacy_path);
+    }
+
     Ok(sock_path)
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-pub enum Animation {
-    None = 0,
-    Left = 1,
-    Right = 2,
-    Up = 3,
-    Down = 4,
-    Fade = 5,
-}
+/// plugin socket in the legacy dir if it's there and nobody else could have put it there.
+/// (anyone can create /tmp/hyprkool)
+fn legacy_plugin_socket(legacy_dir: &Path) -> Option<PathBuf> {
+    let sock_path = legacy_dir.join("plugin.sock");
+    if !sock_path.exists() {
+        return None;
+    }
 
-// TODO: do all this plugin ipc properly
-pub async fn is_plugin_running() -> Result<bool> {
-    _send_plugin_event(Animation::None as _).await
+    // SAFETY: getuid is always successful
+    let uid = unsafe { libc::getuid() };
+    let parent = legacy_dir.parent().unwrap_or(legacy_dir);
+    for path in [parent, legacy_dir, &sock_path] {
+        // symlinks are not followed. they must be ours too
+        match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.uid() == uid => {}
+            Ok(meta) => {
+                warn!(
+                    "ignoring plugin socket at {:?}: {:?} is owned by uid {}",
+                    &sock_path,
+                    path,
+                    meta.uid()
+                );
+                return None;
+            }
+            Err(_) => return None,
+        }
+    }
+    Some(sock_path)
 }
 
-pub async fn set_workspace_anim(anim: Animation) -> Result<()> {
-    match _send_plugin_event(anim as _).await {
-        Ok(_) => {}
-        Err(err) => {
-            println!("could not set workspace animation: {:?}", err);
-            return Err(err);
-        }
+/// only the user running hyprkool gets to talk to it
+pub fn check_peer(stream: &UnixStream) -> Result<()> {
+    let cred = stream.peer_cred()?;
+    // SAFETY: getuid is always successful
+    let uid = unsafe { libc::getuid() };
+    if cred.uid() != uid {
+        return Err(anyhow!(
+            "refusing connection from uid {} (pid {:?})",
+            cred.uid(),
+            cred.pid()
+        ));
     }
     Ok(())
 }
 
-async fn _send_plugin_event(e: usize) -> Result<bool> {
-    let sock_path = get_plugin_socket_path()?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Ok(sock) = UnixStream::connect(&sock_path).await {
-        let mut sock = BufWriter::new(sock);
-        sock.write_all(format!("{}", e).as_bytes()).await?;
-        sock.flush().await?;
-        sock.shutdown().await?;
+    #[test]
+    fn legacy_plugin_socket_owned_by_us() {
+        let root =
+            std::env::temp_dir().join(format!("hyprkool-test-{}-legacy", std::process::id()));
+        let dir = root.join("signature");
+        _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&dir).unwrap();
 
-        let sleep = tokio::time::sleep(Duration::from_millis(300));
-        let mut sock = BufReader::new(sock);
-        let mut line = String::new();
-        tokio::select! {
-            res = sock.read_line(&mut line) => {
-                res?;
-                let command = match serde_json::from_str(&line) {
-                    Ok(c) => c,
-                    Err(e) => {
-                        println!("{}", e);
-                        return Ok(false);
-                    }
-                };
-                match command {
-                    Message::IpcOk => {
-                        println!("Ok");
-                        return Ok(true);
-                    }
-                    Message::IpcErr(message) => {
-                        println!("{}", message);
-                        return Ok(false);
-                    }
-                    _ => {
-                        unreachable!();
-                    }
-                }
-            }
-            _ = sleep => {
-                println!("timeout. could not connect to hyprkool plugin");
-            }
-        }
+        assert_eq!(legacy_plugin_socket(&dir), None);
+
+        let _listener = std::os::unix::net::UnixListener::bind(dir.join("plugin.sock")).unwrap();
+        assert_eq!(legacy_plugin_socket(&dir), Some(dir.join("plugin.sock")));
+
+        _ = std::fs::remove_dir_all(&root);
     }
 
-    Ok(false)
+    #[tokio::test]
+    async fn accepts_peers_of_same_user() {
+        let (a, _b) = UnixStream::pair().unwrap();
+        check_peer(&a).unwrap();
+    }
+
+    #[tokio::test]
+    async fn closes_idle_connections() {
+        let (server, client) = UnixStream::pair().unwrap();
+        let (request_tx, _request_rx) = mpsc::channel(1);
+        let (kevent_tx, _kevent_rx) = mpsc::channel(1);
+        let (kinfo_event_tx, _) = broadcast::channel(1);
+        let info_ctx = InfoCommandContext {
+            config: Default::default(),
+            icons: Default::default(),
+        };
+        let conn = tokio::spawn(KEventListener::process_ipc_conn(
+            server,
+            Duration::from_millis(50),
+            request_tx,
+            kevent_tx,
+            kinfo_event_tx,
+            Arc::new(Mutex::new(info_ctx)),
+        ));
+
+        let mut client = KoolClient::handshake(client).await.unwrap();
+        // the daemon hangs up once the client has been quiet for too long
+        let closed = tokio::time::timeout(Duration::from_secs(5), client.recv()).await;
+        assert!(matches!(closed, Ok(Ok(None))));
+        conn.await.unwrap().unwrap();
+    }
 }
@@ -0,0 +1,241 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::UnixStream;
+
+// NOTE: hyprland-rs does not cover everything hyprctl can do. this talks to hyprland's
+//  control socket directly for the rest.
+
+/// $XDG_RUNTIME_DIR, or what it usually is when it's not set
+pub fn runtime_dir() -> PathBuf {
+    match std::env::var("XDG_RUNTIME_DIR") {
+        Ok(dir) => PathBuf::from(dir),
+        Err(_) => {
+            // SAFETY: getuid is always successful
+            let uid = unsafe { libc::getuid() };
+            PathBuf::from(format!("/run/user/{}", uid))
+        }
+    }
+}
+
+pub fn hyprland_socket_dir() -> Result<PathBuf> {
+    let hypr_signature = std::env::var("HYPRLAND_INSTANCE_SIGNATURE")
+        .context("could not get HYPRLAND_INSTANCE_SIGNATURE")?;
+
+    let mut path = runtime_dir();
+    path.push("hypr");
+    path.push(&hypr_signature);
+    Ok(path)
+}
+
+pub struct Instance {
+    pub signature: String,
+    pub pid: u32,
+    /// unix time at which the instance was started
+    pub time: u64,
+}
+
+/// running hyprland instances. sorted by start time (like `hyprctl instances`)
+pub fn instances() -> Result<Vec<Instance>> {
+    let mut dir = runtime_dir();
+    dir.push("hypr");
+
+    let mut instances = vec![];
+    for entry in std::fs::read_dir(&dir).with_context(|| format!("could not read {:?}", &dir))? {
+        let entry = entry?;
+        let signature = entry.file_name().to_string_lossy().to_string();
+
+        let Ok(lock) = std::fs::read_to_string(entry.path().join("hyprland.lock")) else {
+            continue;
+        };
+        let Some(pid) = lock.lines().next().and_then(|l| l.trim().parse().ok()) else {
+            continue;
+        };
+        if !PathBuf::from(format!("/proc/{}", pid)).exists() {
+            // stale instance dir
+            continue;
+        }
+
+        // signatures look like <commit hash>_<unix time>_<random>
+        let time = signature
+            .split('_')
+            .nth(1)
+            .and_then(|t| t.parse().ok())
+            .unwrap_or(0);
+
+        instances.push(Instance {
+            signature,
+            pid,
+            time,
+        });
+    }
+    instances.sort_by_key(|i| i.time);
+
+    Ok(instances)
+}
+
+/// if the hyprland instance from $HYPRLAND_INSTANCE_SIGNATURE is still running
+pub fn instance_alive() -> bool {
+    let Ok(signature) = std::env::var("HYPRLAND_INSTANCE_SIGNATURE") else {
+        return false;
+    };
+    instances()
+        .map(|instances| instances.iter().any(|i| i.signature == signature))
+        .unwrap_or(false)
+}
+
+/// resolves the value of --instance to an instance signature.
+/// accepts a signature, an index (same as `hyprctl --instance`) or 'auto'.
+/// 'auto' picks the instance from the env if it's running, else the most recently started one.
+pub fn resolve_instance(instance: &str) -> Result<String> {
+    if instance == "auto" {
+        let mut instances = instances()?;
+        if let Ok(signature) = std::env::var("HYPRLAND_INSTANCE_SIGNATURE") {
+            if instances.iter().any(|i| i.signature == signature) {
+                return Ok(signature);
+            }
+        }
+        return instances
+            .pop()
+            .map(|i| i.signature)
+            .context("no running hyprland instance found");
+    }
+
+    if let Ok(index) = instance.parse::<usize>() {
+        return instances()?
+            .into_iter()
+            .nth(index)
+            .map(|i| i.signature)
+            .with_context(|| format!("no hyprland instance with index {}", index));
+    }
+
+    Ok(instance.to_string())
+}
+
+/// same as `hyprctl <request>`
+pub async fn request(request: &str) -> Result<String> {
+    let mut path = hyprland_socket_dir()?;
+    path.push(".socket.sock");
+
+    let mut sock = UnixStream::connect(&path)
+        .await
+        .with_context(|| format!("could not connect to hyprland socket at {:?}", &path))?;
+    sock.write_all(request.as_bytes()).await?;
+    sock.flush().await?;
+
+    let mut out = String::new();
+    sock.read_to_string(&mut out).await?;
+    Ok(out)
+}
+
+/// name of the current submap. empty if no submap is active (same as submap events)
+pub async fn current_submap() -> Result<String> {
+    let submap = request("submap").await?;
+    let submap = submap.trim();
+    if submap == "default" {
+        return Ok(String::new());
+    }
+    Ok(submap.to_string())
+}
+
+/// one entry of `hyprctl animations`. values are inherited from the parent if not overridden.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AnimationConfig {
+    pub name: String,
+    pub overridden: bool,
+    pub bezier: String,
+    pub enabled: bool,
+    pub speed: f32,
+    pub style: String,
+}
+
+pub async fn animation(name: &str) -> Result<AnimationConfig> {
+    let out = request("j/animations").await?;
+    // [[animations], [beziers]]
+    let (animations, _beziers): (Vec<AnimationConfig>, serde_json::Value) =
+        serde_json::from_str(&out).context("could not parse hyprctl animations")?;
+    animations
+        .into_iter()
+        .find(|a| a.name == name)
+        .with_context(|| format!("hyprland has no animation named {}", name))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Workspace {
+    id: i64,
+    name: String,
+}
+
+/// id of the workspace with this name. None if it does not exist
+pub async fn workspace_id(name: &str) -> Result<Option<i64>> {
+    let out = request("j/workspaces").await?;
+    let workspaces: Vec<Workspace> =
+        serde_json::from_str(&out).context("could not parse hyprctl workspaces")?;
+    Ok(workspaces
+        .into_iter()
+        .find(|w| w.name == name)
+        .map(|w| w.id))
+}
+
+/// same as `hyprctl keyword <keyword> <value>`
+pub async fn keyword(keyword: &str, value: &str) -> Result<()> {
+    let out = request(&format!("keyword {} {}", keyword, value)).await?;
+    if out.trim() != "ok" {
+        return Err(anyhow!(
+            "hyprctl keyword {} {}: {}",
+            keyword,
+            value,
+            out.trim()
+        ));
+    }
+    Ok(())
+}
+
+/// dispatches that are sent to hyprland in a single `[[BATCH]]` request.
+/// hyprland runs all of them before handling anything else, so there's no flicker in between.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DispatchBatch {
+    commands: Vec<String>,
+}
+
+impl DispatchBatch {
+    /// same as `hyprctl dispatch <dispatcher> <args>`
+    pub fn dispatch(&mut self, dispatcher: &str, args: &str) -> &mut Self {
+        self.commands
+            .push(format!("dispatch {} {}", dispatcher, args));
+        self
+    }
+
+    pub fn commands(&self) -> &[String] {
+        &self.commands
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    pub async fn send(self) -> Result<()> {
+        if self.commands.is_empty() {
+            return Ok(());
+        }
+
+        let out = request(&format!("[[BATCH]]{}", self.commands.join(";"))).await?;
+
+        // one reply per command
+        let errors = out
+            .split("\n\n")
+            .map(str::trim)
+            .filter(|r| !r.is_empty() && *r != "ok")
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            return Err(anyhow!(
+                "hyprland rejected dispatch batch {:?}: {}",
+                self.commands,
+                errors.join(", ")
+            ));
+        }
+        Ok(())
+    }
+}
@@ -4,7 +4,9 @@ use std::path::PathBuf;
 use linicon::IconPath;
 use serde::{Deserialize, Serialize};
 
+use crate::command::Command;
 use crate::config::Config;
+use crate::error::ErrorKind;
 
 pub struct InfoCommandContext {
     pub config: Config,
@@ -82,6 +84,48 @@ impl InfoCommandContext {
 pub enum KInfoEvent {
     Submap(SubmapStatus),
     Monitors(Vec<MonitorStatus>),
+    EdgeSwitch(EdgeSwitchStatus),
+    Event(KoolEvent),
+}
+
+/// things that changed. streamed by `hyprkool info events`
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum KoolEvent {
+    /// activity is null when the workspace is not a hyprkool workspace
+    ActivityChanged {
+        monitor: String,
+        from: Option<String>,
+        to: Option<String>,
+    },
+    WorkspaceChanged {
+        monitor: String,
+        from: String,
+        to: String,
+    },
+    MonitorFocused {
+        from: Option<String>,
+        to: String,
+    },
+    MonitorAdded {
+        monitor: String,
+    },
+    MonitorRemoved {
+        monitor: String,
+    },
+    /// workspace is null when the named focus is removed
+    NamedFocusSet {
+        name: String,
+        workspace: Option<String>,
+    },
+    EdgeSwitched {
+        monitor: String,
+        from: String,
+        to: String,
+    },
+    SubmapChanged {
+        submap: String,
+    },
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -89,6 +133,16 @@ pub struct SubmapStatus {
     pub submap: String,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EdgeSwitchStatus {
+    /// enabled in config and not disabled using the edge-switch command
+    pub enabled: bool,
+    /// class of the focused window that is inhibiting edge switching
+    pub inhibited_by: Option<String>,
+    /// edge switching will happen if cursor touches screen edges
+    pub active: bool,
+}
+
 #[derive(Serialize, Debug, Clone)]
 pub struct MonitorStatus {
     pub name: String,
@@ -123,3 +177,50 @@ pub struct WindowStatus {
     pub focused: bool,
     pub focus_history_id: i32,
 }
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkspaceQuery {
+    pub workspace: String,
+    /// these are null if workspace is not a hyprkool workspace
+    pub activity: Option<String>,
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+    /// monitor this workspace is active on
+    pub monitor: Option<String>,
+    pub named_focus: Vec<String>,
+}
+
+/// result of a command as printed by `hyprkool shell` (and with `--json`)
+#[derive(Serialize, Debug, Clone)]
+pub struct CommandResult {
+    pub ok: bool,
+    pub error: Option<String>,
+    pub data: Option<serde_json::Value>,
+}
+
+impl From<anyhow::Result<Option<serde_json::Value>>> for CommandResult {
+    fn from(res: anyhow::Result<Option<serde_json::Value>>) -> Self {
+        match res {
+            Ok(data) => Self {
+                ok: true,
+                error: None,
+                data,
+            },
+            Err(e) => Self {
+                ok: false,
+                error: Some(format!("{}", e)),
+                data: None,
+            },
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct BatchStepResult {
+    pub command: Command,
+    pub ok: bool,
+    pub error: Option<String>,
+    /// null if the step did not fail
+    pub kind: Option<ErrorKind>,
+    pub data: Option<serde_json::Value>,
+}
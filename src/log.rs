@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::{LogConfig, LogTarget};
+use crate::status::ErrorLog;
+
+/// $XDG_STATE_HOME/hyprkool
+pub fn default_log_dir() -> Option<PathBuf> {
+    dirs::state_dir().map(|d| d.join("hyprkool"))
+}
+
+fn filter(level: &str) -> Result<EnvFilter> {
+    if let Ok(filter) = EnvFilter::try_from_default_env() {
+        return Ok(filter);
+    }
+    EnvFilter::try_new(level).with_context(|| format!("invalid log level '{}'", level))
+}
+
+/// logging for the daemon. errors also go to `errors` (for `hyprkool status`)
+pub fn init_daemon(config: &LogConfig, errors: ErrorLog) -> Result<()> {
+    let target = match config.target {
+        // systemd sets this when stderr is connected to the journal
+        LogTarget::Auto if std::env::var_os("JOURNAL_STREAM").is_some() => LogTarget::Journald,
+        LogTarget::Auto => LogTarget::Stderr,
+        target => target,
+    };
+
+    let mut journald_err = None;
+    let journald = match target {
+        LogTarget::Journald => match tracing_journald::layer() {
+            Ok(layer) => Some(layer),
+            Err(e) => {
+                journald_err = Some(e);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    let file = match target {
+        LogTarget::File => {
+            let dir = config
+                .dir
+                .clone()
+                .or_else(default_log_dir)
+                .context("could not find a directory for log files")?;
+            let appender = RollingFileAppender::builder()
+                .rotation(Rotation::DAILY)
+                .filename_prefix("hyprkool")
+                .filename_suffix("log")
+                .max_log_files(config.max_files.max(1))
+                .build(&dir)
+                .with_context(|| format!("could not create log file in {:?}", &dir))?;
+            Some(
+                tracing_subscriber::fmt::layer()
+                    .with_ansi(false)
+                    .with_writer(appender),
+            )
+        }
+        _ => None,
+    };
+
+    let stderr = (journald.is_none() && file.is_none())
+        .then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr));
+
+    tracing_subscriber::registry()
+        .with(filter(&config.level)?)
+        .with(stderr)
+        .with(file)
+        .with(journald)
+        .with(errors)
+        .try_init()?;
+
+    if let Some(e) = journald_err {
+        tracing::warn!("journald not available, logging to stderr: {}", e);
+    }
+    Ok(())
+}
+
+/// logging for everything other than the daemon. stdout is left alone for command output
+pub fn init_cli() -> Result<()> {
+    tracing_subscriber::registry()
+        .with(filter("warn")?)
+        .with(
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .without_time(),
+        )
+        .try_init()?;
+    Ok(())
+}
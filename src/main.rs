@@ -4,28 +4,66 @@ use std::time::Duration;
 
 use anyhow::Result;
 use clap::Parser;
-use tokio::io::BufWriter;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
 
+mod animation;
 mod command;
 mod config;
+mod error;
 mod event;
+mod hyprctl;
 mod info;
+mod log;
+mod plugin;
 mod state;
+mod status;
+mod systemd;
 
 use command::Command;
+use command::InfoCommand;
 use config::Cli;
+use config::Config;
+use error::ErrorKind;
+use error::KoolError;
+use event::connect_daemon;
 use event::daemon;
-use event::get_socket_path;
+use event::info_without_daemon;
+use event::shell;
 use event::Message;
+use event::VERSION;
+use hyprctl::resolve_instance;
+use info::BatchStepResult;
+use info::CommandResult;
+use plugin::PluginClient;
 use state::State;
+use status::ErrorLog;
+use status::Status;
 
-#[tokio::main]
-async fn main() -> Result<()> {
+fn main() -> Result<()> {
     let cli = Cli::parse();
 
-    let sock_path = get_socket_path()?;
+    // NOTE: the env is only changed here, before the runtime starts any threads.
+    //  changing it while other threads might be reading it is not safe.
+    if let Some(instance) = &cli.instance {
+        let signature = resolve_instance(instance)?;
+        // NOTE: everything (including hyprland-rs) picks the instance from the env
+        std::env::set_var("HYPRLAND_INSTANCE_SIGNATURE", signature);
+    }
+    let systemd_sock = match cli.command {
+        Command::Daemon => systemd::listen_fds()?,
+        _ => None,
+    };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(run(cli, systemd_sock))
+}
+
+/// `systemd_sock` is the daemon's socket if systemd passed one
+async fn run(cli: Cli, systemd_sock: Option<std::os::unix::net::UnixListener>) -> Result<()> {
+    if cli.command != Command::Daemon {
+        log::init_cli()?;
+    }
 
     match cli.command.clone() {
         Command::Daemon => {
@@ -34,127 +72,251 @@ async fn main() -> Result<()> {
                 return Ok(());
             }
 
-            daemon(cli.config()?).await?;
-            println!("exiting daemon");
+            let config = cli.config()?;
+            let errors = ErrorLog::default();
+            log::init_daemon(&config.daemon.log, errors.clone())?;
+            daemon(config, cli.config_path(), errors, systemd_sock).await?;
+            tracing::info!("exiting daemon");
+        }
+        Command::Status => {
+            let config = cli.config()?;
+            let res = status(config, cli.force_no_daemon).await;
+            let code = match &res {
+                Ok(s) if s.daemon.is_none() => ErrorKind::DaemonUnreachable.exit_code(),
+                Ok(_) => 0,
+                Err(e) => e.kind.exit_code(),
+            };
+            match res {
+                Ok(s) if !cli.json => print!("{}", s),
+                res => {
+                    report(res.map(|s| serde_json::to_value(s).ok()), cli.json);
+                }
+            }
+            std::process::exit(code);
+        }
+        Command::Shell => {
+            shell(cli.config()?).await?;
         }
         Command::Info { command, monitor } => {
-            if !cli.force_no_daemon {
-                if let Ok(sock) = UnixStream::connect(&sock_path).await {
-                    let mut sock = BufWriter::new(sock);
-                    sock.write_all(
-                        &Message::Command(Command::Info {
-                            command: command.clone(),
-                            monitor,
-                        })
-                        .msg(),
+            let config = cli.config()?;
+            let code = info(config, command, monitor, cli.force_no_daemon, cli.json).await;
+            std::process::exit(code);
+        }
+        cmd => {
+            let config = cli.config()?;
+            let is_batch = matches!(cmd, Command::Batch { .. });
+            let res = run_command(config, cmd, cli.force_no_daemon).await;
+            // a batch is printed in full even if some of its steps failed
+            let batch_code = match &res {
+                Ok(Some(data)) if is_batch => batch_exit_code(data),
+                _ => 0,
+            };
+            let code = report(res, cli.json);
+            std::process::exit(if code != 0 { code } else { batch_code });
+        }
+    }
+
+    Ok(())
+}
+
+fn daemon_not_running() -> KoolError {
+    KoolError::new(
+        ErrorKind::DaemonUnreachable,
+        "hyprkool daemon is not running (and fallback_commands is disabled)",
+    )
+}
+
+fn daemon_unreachable(e: anyhow::Error) -> KoolError {
+    KoolError::new(ErrorKind::DaemonUnreachable, e.to_string())
+}
+
+/// status from the daemon if it is running, else whatever the cli can find out on its own
+async fn status(config: Config, force_no_daemon: bool) -> std::result::Result<Status, KoolError> {
+    let timeout = Duration::from_millis(config.daemon.ipc_timeout);
+
+    if !force_no_daemon {
+        if let Some(mut client) = connect_daemon(&config.daemon)
+            .await
+            .map_err(daemon_unreachable)?
+        {
+            let data = client
+                .execute(Command::Status, timeout)
+                .await
+                .map_err(daemon_unreachable)??;
+            let mut status: Status =
+                serde_json::from_value(data.unwrap_or_default()).map_err(|e| {
+                    KoolError::new(
+                        ErrorKind::Other,
+                        format!("unexpected status from daemon: {}", e),
                     )
-                    .await?;
-                    sock.flush().await?;
-                    sock.shutdown().await?;
+                })?;
+            status.version = VERSION.to_string();
+            return Ok(status);
+        }
+    }
 
-                    let mut sock = BufReader::new(sock);
-                    loop {
-                        let mut line = String::new();
-                        let _ = sock.read_line(&mut line).await?;
+    Ok(Status::new(None, &mut PluginClient::new(timeout), timeout).await)
+}
 
-                        if !monitor && line.is_empty() {
-                            return Ok(());
-                        }
+/// streams info from the daemon (or statelessly if there is no daemon) and returns the exit code
+async fn info(
+    config: Config,
+    command: InfoCommand,
+    monitor: bool,
+    force_no_daemon: bool,
+    json: bool,
+) -> i32 {
+    if !force_no_daemon {
+        let client = match connect_daemon(&config.daemon).await {
+            Ok(client) => client,
+            Err(e) => return report(Err(daemon_unreachable(e)), json),
+        };
+        if let Some(mut client) = client {
+            let mut code = 0;
+            let res = async {
+                client
+                    .send(Message::Command(Command::Info { command, monitor }))
+                    .await?;
+                client.finish().await?;
 
-                        if line.is_empty() {
-                            continue;
+                while let Some(packet) = client.recv().await? {
+                    match packet.message {
+                        Message::IpcMessage(message) => {
+                            println!("{}", message);
                         }
-
-                        let command = serde_json::from_str(&line)?;
-                        match command {
-                            Message::IpcMessage(message) => {
-                                println!("{}", message);
-                            }
-                            Message::IpcErr(message) => {
-                                println!("{}", message);
-                            }
-                            _ => {
-                                unreachable!();
-                            }
+                        Message::IpcErr(message) => {
+                            code = report(Err(KoolError::new(ErrorKind::Rejected, message)), json);
+                        }
+                        Message::Error(e) => {
+                            code = report(Err(e), json);
+                        }
+                        m => {
+                            eprintln!("unexpected message from daemon: {:?}", m);
                         }
                     }
                 }
-
-                let config = cli.config()?;
-                if !config.daemon.fallback_commands {
-                    return Ok(());
-                }
-                dbg!("falling back to stateless commands");
+                anyhow::Ok(())
+            }
+            .await;
+            if let Err(e) = res {
+                return report(Err(daemon_unreachable(e)), json);
             }
+            return code;
+        }
 
-            // let state = match State::new(cli.config()?) {
-            //     Ok(s) => s,
-            //     Err(e) => {
-            //         println!("{}", e);
-            //         return Ok(());
-            //     }
-            // };
-            // command
-            //     .execute(
-            //         InfoOutputStream::Stdout,
-            //         Arc::new(Mutex::new(state)),
-            //         monitor,
-            //     )
-            //     .await?;
-            todo!("info command are currently only supported with the daemon running. run 'hyprkool daemon'");
+        if !config.daemon.fallback_commands {
+            return report(Err(daemon_not_running()), json);
         }
-        cmd => {
-            if !cli.force_no_daemon {
-                if let Ok(sock) = UnixStream::connect(&sock_path).await {
-                    let mut sock = BufWriter::new(sock);
-                    sock.write_all(&Message::Command(cmd.clone()).msg()).await?;
-                    sock.flush().await?;
-                    sock.shutdown().await?;
-
-                    let sleep = tokio::time::sleep(Duration::from_millis(300));
-                    let mut sock = BufReader::new(sock);
-                    let mut line = String::new();
-                    tokio::select! {
-                        res = sock.read_line(&mut line) => {
-                            res?;
-                            let command = serde_json::from_str(&line)?;
-                            match command {
-                                Message::IpcOk => {
-                                    println!("Ok");
-                                    return Ok(());
-                                }
-                                Message::IpcErr(message) => {
-                                    println!("{}", message);
-                                    return Ok(());
-                                }
-                                _ => {
-                                    unreachable!();
-                                }
-                            }
-                        }
-                        _ = sleep => {
-                            println!("timeout. could not connect to hyprkool");
-                        }
-                    }
-                }
+        eprintln!("falling back to stateless commands");
+    }
 
-                let config = cli.config()?;
-                if !config.daemon.fallback_commands {
-                    return Ok(());
-                }
-                println!("falling back to stateless commands");
-            }
+    match info_without_daemon(config, command, monitor).await {
+        Ok(()) => 0,
+        Err(e) => report(Err(KoolError::from_anyhow(&e, ErrorKind::Rejected)), json),
+    }
+}
 
-            let mut state = match State::new(cli.config()?).await {
-                Ok(s) => s,
-                Err(e) => {
-                    println!("{}", e);
-                    return Ok(());
-                }
-            };
-            state.execute(cmd, None).await?;
+/// exit code of the first step of a batch that failed
+fn batch_exit_code(data: &serde_json::Value) -> i32 {
+    let Ok(steps) = serde_json::from_value::<Vec<BatchStepResult>>(data.clone()) else {
+        return 0;
+    };
+    steps
+        .iter()
+        .find(|s| !s.ok)
+        .map(|s| s.kind.unwrap_or(ErrorKind::Rejected).exit_code())
+        .unwrap_or(0)
+}
+
+/// runs the command on the daemon, or statelessly if there is no daemon
+async fn run_command(
+    config: Config,
+    cmd: Command,
+    force_no_daemon: bool,
+) -> std::result::Result<Option<serde_json::Value>, KoolError> {
+    if !force_no_daemon {
+        if let Some(mut client) = connect_daemon(&config.daemon)
+            .await
+            .map_err(daemon_unreachable)?
+        {
+            // NOTE: the daemon has the command once it is sent. falling back to stateless commands
+            //  after this point could apply the command twice.
+            let timeout = Duration::from_millis(config.daemon.ipc_timeout);
+            return client
+                .execute(cmd, timeout)
+                .await
+                .map_err(daemon_unreachable)?;
         }
+
+        if !config.daemon.fallback_commands {
+            return Err(daemon_not_running());
+        }
+        eprintln!("falling back to stateless commands");
     }
 
-    Ok(())
+    let mut state = State::new(config)
+        .await
+        .map_err(|e| KoolError::from_anyhow(&e, ErrorKind::Other))?;
+    state
+        .execute(cmd, None)
+        .await
+        .map_err(|e| KoolError::from_anyhow(&e, ErrorKind::Rejected))
+}
+
+/// prints the result and returns the exit code
+fn report(res: std::result::Result<Option<serde_json::Value>, KoolError>, json: bool) -> i32 {
+    let code = match &res {
+        Ok(_) => 0,
+        Err(e) => e.kind.exit_code(),
+    };
+
+    if json {
+        let res = CommandResult::from(res.map_err(anyhow::Error::from));
+        match serde_json::to_string(&res) {
+            Ok(s) => println!("{}", s),
+            Err(e) => eprintln!("could not serialize result: {}", e),
+        }
+        return code;
+    }
+
+    match res {
+        Ok(data) => match Message::reply(data) {
+            Message::IpcMessage(message) => println!("{}", message),
+            Message::IpcData(data) => println!("{}", data),
+            _ => println!("Ok"),
+        },
+        Err(e) => eprintln!("error: {}", e),
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn step(ok: bool, kind: Option<ErrorKind>) -> BatchStepResult {
+        BatchStepResult {
+            command: Command::ToggleOverview,
+            ok,
+            error: kind.map(|_| "failed".to_string()),
+            kind,
+            data: None,
+        }
+    }
+
+    #[test]
+    fn batch_exits_with_first_failure() {
+        let code = |steps: Vec<BatchStepResult>| {
+            batch_exit_code(&serde_json::to_value(steps).expect("could not serialize"))
+        };
+        assert_eq!(code(vec![step(true, None), step(true, None)]), 0);
+        assert_eq!(
+            code(vec![
+                step(true, None),
+                step(false, Some(ErrorKind::NotInKoolWorkspace)),
+                step(false, Some(ErrorKind::Rejected)),
+            ]),
+            4
+        );
+    }
 }
@@ -0,0 +1,529 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::UnixStream;
+use tracing::warn;
+
+use crate::event::check_peer;
+use crate::event::get_plugin_socket_path;
+use crate::event::VERSION;
+
+// NOTE: plugin/src/main.cpp parses these by hand. it only understands flat objects,
+//  so keep requests free of nested values.
+
+/// bump this whenever the messages exchanged between daemon and plugin change
+/// (keep in sync with KOOL_PLUGIN_PROTOCOL_VERSION in plugin/src/main.cpp)
+pub const PLUGIN_PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Animation {
+    None,
+    Left,
+    Right,
+    Up,
+    Down,
+    Fade,
+}
+
+impl Animation {
+    /// slide towards (x, y). fade if it is not along one axis
+    pub fn towards(x: i32, y: i32) -> Self {
+        if x > 0 && y == 0 {
+            return Self::Right;
+        }
+        if x < 0 && y == 0 {
+            return Self::Left;
+        }
+        if x == 0 && y > 0 {
+            return Self::Down;
+        }
+        if x == 0 && y < 0 {
+            return Self::Up;
+        }
+
+        Self::Fade
+    }
+}
+
+/// every line sent to the plugin socket is a request. the plugin answers each one with
+/// exactly one [`PluginResponse`] line.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginRequest {
+    Hello {
+        protocol: u32,
+        version: String,
+    },
+    /// animation for the next workspace change
+    SetAnimation {
+        animation: Animation,
+        /// in ms. hyprland's configured speed if not set
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        duration: Option<u32>,
+        /// name of a bezier curve defined in hyprland's config
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        curve: Option<String>,
+    },
+    /// address of the window currently being dragged with the mouse
+    DraggedWindow,
+    /// mask of keyboard modifiers currently held (same bits as hyprland's modmask)
+    Modifiers,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum PluginResponse {
+    Hello {
+        protocol: u32,
+        version: String,
+        capabilities: Vec<Capability>,
+    },
+    Ok,
+    Err {
+        message: String,
+    },
+    DraggedWindow {
+        address: Option<String>,
+    },
+    Modifiers {
+        mask: u32,
+    },
+}
+
+/// what a plugin build can do. newer plugins might report capabilities we don't know about yet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    SetAnimation,
+    AnimationDuration,
+    AnimationCurve,
+    DraggedWindow,
+    Modifiers,
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PluginInfo {
+    pub version: String,
+    pub capabilities: Vec<Capability>,
+}
+
+impl PluginInfo {
+    pub fn supports(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "info", rename_all = "snake_case")]
+pub enum PluginStatus {
+    /// not checked yet (or the cached status is stale)
+    Unknown,
+    Absent,
+    /// loaded, but speaks a different protocol. it is not used.
+    Incompatible(String),
+    Loaded(PluginInfo),
+}
+
+pub struct PluginClient {
+    /// the default plugin socket of the current hyprland instance if not set
+    sock_path: Option<PathBuf>,
+    timeout: Duration,
+    status: PluginStatus,
+}
+
+impl PluginClient {
+    pub fn new(timeout: Duration) -> Self {
+        Self {
+            sock_path: None,
+            timeout,
+            status: PluginStatus::Unknown,
+        }
+    }
+
+    /// talk to whatever is listening on this socket instead of the plugin
+    pub fn with_socket(sock_path: PathBuf, timeout: Duration) -> Self {
+        Self {
+            sock_path: Some(sock_path),
+            timeout,
+            status: PluginStatus::Unknown,
+        }
+    }
+
+    /// Ok(None) if the plugin is not running
+    pub async fn request(&self, req: &PluginRequest) -> Result<Option<PluginResponse>> {
+        let sock_path = match &self.sock_path {
+            Some(p) => p.clone(),
+            None => get_plugin_socket_path()?,
+        };
+
+        let Ok(sock) = UnixStream::connect(&sock_path).await else {
+            return Ok(None);
+        };
+        // the plugin runs inside hyprland, which runs as us
+        check_peer(&sock)
+            .with_context(|| format!("not using plugin socket at {:?}", &sock_path))?;
+        let (reader, mut writer) = sock.into_split();
+
+        let mut line = serde_json::to_string(req)?;
+        line.push('\n');
+        writer.write_all(line.as_bytes()).await?;
+        writer.flush().await?;
+        writer.shutdown().await?;
+
+        let mut reader = BufReader::new(reader);
+        let mut line = String::new();
+        tokio::time::timeout(self.timeout, reader.read_line(&mut line))
+            .await
+            .context("timeout. hyprkool plugin did not reply (it might be an older version)")??;
+        if line.is_empty() {
+            return Err(anyhow!(
+                "hyprkool plugin closed the connection (it might be an older version)"
+            ));
+        }
+
+        let res = serde_json::from_str(&line)
+            .with_context(|| format!("could not parse reply from plugin: {}", line.trim()))?;
+        match res {
+            PluginResponse::Err { message } => Err(anyhow!("plugin error: {}", message)),
+            res => Ok(Some(res)),
+        }
+    }
+
+    /// Ok(None) if the plugin is not running
+    pub async fn hello(&self) -> Result<Option<PluginInfo>> {
+        let req = PluginRequest::Hello {
+            protocol: PLUGIN_PROTOCOL_VERSION,
+            version: VERSION.to_string(),
+        };
+        match self.request(&req).await? {
+            Some(PluginResponse::Hello {
+                protocol,
+                version,
+                capabilities,
+            }) => {
+                if protocol != PLUGIN_PROTOCOL_VERSION {
+                    return Err(anyhow!(
+                        "hyprkool plugin is version {} (protocol {}), daemon is version {} (protocol {}). update hyprkool and reload the plugin",
+                        version,
+                        protocol,
+                        VERSION,
+                        PLUGIN_PROTOCOL_VERSION
+                    ));
+                }
+                Ok(Some(PluginInfo {
+                    version,
+                    capabilities,
+                }))
+            }
+            Some(res) => Err(anyhow!("unexpected reply from plugin: {:?}", res)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn status(&self) -> &PluginStatus {
+        &self.status
+    }
+
+    /// forget what we know about the plugin. it is checked again before the next plugin call.
+    pub fn invalidate(&mut self) {
+        self.status = PluginStatus::Unknown;
+    }
+
+    /// checks if the plugin is loaded and compatible. the result is cached till [`Self::invalidate`]
+    pub async fn detect(&mut self) -> &PluginStatus {
+        if self.status != PluginStatus::Unknown {
+            return &self.status;
+        }
+
+        self.status = match self.hello().await {
+            Ok(Some(info)) => {
+                if info.version != VERSION {
+                    warn!(
+                        "hyprkool plugin is version {}, daemon is version {}. consider reloading the plugin",
+                        info.version, VERSION
+                    );
+                }
+                PluginStatus::Loaded(info)
+            }
+            Ok(None) => PluginStatus::Absent,
+            Err(e) => {
+                warn!("not using hyprkool plugin: {}", e);
+                PluginStatus::Incompatible(e.to_string())
+            }
+        };
+        &self.status
+    }
+
+    /// if the plugin can be used for this. never touches the socket if it is known to be absent.
+    pub async fn supports(&mut self, capability: Capability) -> bool {
+        match self.detect().await {
+            PluginStatus::Loaded(info) => info.supports(capability),
+            _ => false,
+        }
+    }
+
+    /// like [`Self::request`], but remembers if the plugin went away
+    async fn call(&mut self, req: &PluginRequest) -> Result<Option<PluginResponse>> {
+        let res = self.request(req).await;
+        if let Ok(None) = &res {
+            self.status = PluginStatus::Absent;
+        }
+        res
+    }
+
+    /// duration (in ms) and curve are left to hyprland's config if not set (or not supported by the plugin)
+    pub async fn set_animation(
+        &mut self,
+        animation: Animation,
+        duration: Option<u32>,
+        curve: Option<String>,
+    ) -> Result<()> {
+        let (duration, curve) = match self.detect().await {
+            PluginStatus::Loaded(info) if info.supports(Capability::SetAnimation) => (
+                duration.filter(|_| info.supports(Capability::AnimationDuration)),
+                curve.filter(|_| info.supports(Capability::AnimationCurve)),
+            ),
+            _ => return Ok(()),
+        };
+
+        let req = PluginRequest::SetAnimation {
+            animation,
+            duration,
+            curve,
+        };
+        match self.call(&req).await {
+            Ok(Some(PluginResponse::Ok)) | Ok(None) => Ok(()),
+            Ok(Some(res)) => Err(anyhow!("unexpected reply from plugin: {:?}", res)),
+            Err(err) => {
+                warn!("could not set workspace animation: {:?}", err);
+                Err(err)
+            }
+        }
+    }
+
+    /// address of the window currently being dragged with the mouse (if any)
+    pub async fn dragged_window(&mut self) -> Result<Option<String>> {
+        if !self.supports(Capability::DraggedWindow).await {
+            return Ok(None);
+        }
+        match self.call(&PluginRequest::DraggedWindow).await? {
+            Some(PluginResponse::DraggedWindow { address }) => Ok(address),
+            Some(res) => Err(anyhow!("unexpected reply from plugin: {:?}", res)),
+            None => Ok(None),
+        }
+    }
+
+    /// mask of keyboard modifiers currently held (same bits as hyprland's modmask)
+    pub async fn modifiers(&mut self) -> Result<Option<u32>> {
+        if !self.supports(Capability::Modifiers).await {
+            return Ok(None);
+        }
+        match self.call(&PluginRequest::Modifiers).await? {
+            Some(PluginResponse::Modifiers { mask }) => Ok(Some(mask)),
+            Some(res) => Err(anyhow!("unexpected reply from plugin: {:?}", res)),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod mock {
+    use tokio::net::UnixListener;
+    use tokio::task::JoinHandle;
+
+    use super::*;
+
+    /// pretends to be the plugin. answers the n-th connection with `replies[n]`
+    /// and returns the requests it got.
+    pub struct MockPlugin {
+        dir: PathBuf,
+        requests: JoinHandle<Vec<PluginRequest>>,
+    }
+
+    impl MockPlugin {
+        pub fn new(name: &str, replies: &[&str]) -> Self {
+            let replies = replies
+                .iter()
+                .map(|r| format!("{}\n", r))
+                .collect::<Vec<_>>();
+            Self::serve(name, |listener| async move {
+                let mut requests = vec![];
+                for reply in replies {
+                    let (sock, _) = listener.accept().await.expect("could not accept");
+                    let (reader, mut writer) = sock.into_split();
+                    let mut line = String::new();
+                    BufReader::new(reader)
+                        .read_line(&mut line)
+                        .await
+                        .expect("could not read request");
+                    requests.push(serde_json::from_str(&line).expect("invalid request"));
+                    writer
+                        .write_all(reply.as_bytes())
+                        .await
+                        .expect("could not reply");
+                }
+                requests
+            })
+        }
+
+        /// accepts a connection, but never replies
+        pub fn silent(name: &str) -> Self {
+            Self::serve(name, |listener| async move {
+                let (_sock, _) = listener.accept().await.expect("could not accept");
+                std::future::pending().await
+            })
+        }
+
+        fn serve<F: std::future::Future<Output = Vec<PluginRequest>> + Send + 'static>(
+            name: &str,
+            serve: impl FnOnce(UnixListener) -> F,
+        ) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("hyprkool-test-{}-{}", std::process::id(), name));
+            _ = std::fs::remove_dir_all(&dir);
+            std::fs::create_dir_all(&dir).expect("could not create test dir");
+
+            let listener = UnixListener::bind(dir.join("plugin.sock")).expect("could not bind");
+            let requests = tokio::spawn(serve(listener));
+            Self { dir, requests }
+        }
+
+        pub fn socket(&self) -> PathBuf {
+            self.dir.join("plugin.sock")
+        }
+
+        pub fn client(&self) -> PluginClient {
+            PluginClient::with_socket(self.socket(), Duration::from_secs(1))
+        }
+
+        pub async fn requests(self) -> Vec<PluginRequest> {
+            let requests = self.requests.await.expect("mock plugin panicked");
+            _ = std::fs::remove_dir_all(&self.dir);
+            requests
+        }
+
+        pub fn stop(self) {
+            self.requests.abort();
+            _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    pub fn hello(protocol: u32) -> String {
+        format!(
+            r#"{{"type":"hello","protocol":{},"version":"{}","capabilities":["set_animation","animation_duration","dragged_window","new_thing"]}}"#,
+            protocol, VERSION
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{hello, MockPlugin};
+    use super::*;
+
+    #[tokio::test]
+    async fn detects_plugin() {
+        let mock = MockPlugin::new("hello", &[&hello(PLUGIN_PROTOCOL_VERSION)]);
+        let mut client = mock.client();
+        assert_eq!(
+            client.detect().await,
+            &PluginStatus::Loaded(PluginInfo {
+                version: VERSION.to_string(),
+                capabilities: vec![
+                    Capability::SetAnimation,
+                    Capability::AnimationDuration,
+                    Capability::DraggedWindow,
+                    Capability::Unknown,
+                ],
+            })
+        );
+        assert_eq!(
+            mock.requests().await,
+            [PluginRequest::Hello {
+                protocol: PLUGIN_PROTOCOL_VERSION,
+                version: VERSION.to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn protocol_mismatch_is_incompatible() {
+        let mock = MockPlugin::new("mismatch", &[&hello(PLUGIN_PROTOCOL_VERSION + 1)]);
+        let mut client = mock.client();
+        assert!(matches!(
+            client.detect().await,
+            PluginStatus::Incompatible(_)
+        ));
+        // incompatible plugins are not used
+        assert!(!client.supports(Capability::SetAnimation).await);
+        client
+            .set_animation(Animation::Left, None, None)
+            .await
+            .unwrap();
+        assert_eq!(mock.requests().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn no_socket_is_absent() {
+        let dir = std::env::temp_dir().join(format!("hyprkool-test-{}-absent", std::process::id()));
+        let mut client = PluginClient::with_socket(dir.join("plugin.sock"), Duration::from_secs(1));
+        assert_eq!(client.detect().await, &PluginStatus::Absent);
+        client
+            .set_animation(Animation::Left, None, None)
+            .await
+            .unwrap();
+        assert_eq!(client.dragged_window().await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn sets_animation() {
+        let mock = MockPlugin::new(
+            "set-animation",
+            &[&hello(PLUGIN_PROTOCOL_VERSION), r#"{"type":"ok"}"#],
+        );
+        let mut client = mock.client();
+        client
+            .set_animation(Animation::Up, Some(200), Some("linear".into()))
+            .await
+            .unwrap();
+        let requests = mock.requests().await;
+        // the plugin does not support animation_curve
+        assert_eq!(
+            requests[1],
+            PluginRequest::SetAnimation {
+                animation: Animation::Up,
+                duration: Some(200),
+                curve: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn err_replies_are_errors() {
+        let mock = MockPlugin::new(
+            "err",
+            &[
+                &hello(PLUGIN_PROTOCOL_VERSION),
+                r#"{"type":"err","message":"unknown animation"}"#,
+                r#"{"type":"modifiers","mask":64}"#,
+            ],
+        );
+        let mut client = mock.client();
+        let err = client
+            .set_animation(Animation::Fade, None, None)
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "plugin error: unknown animation");
+        // the plugin is still there
+        assert!(client.supports(Capability::DraggedWindow).await);
+        let err = client.dragged_window().await.unwrap_err();
+        assert!(err.to_string().starts_with("unexpected reply from plugin"));
+        assert_eq!(mock.requests().await.len(), 3);
+    }
+}
@@ -1,51 +1,213 @@
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::time::Duration;
 
 use anyhow::{anyhow, Context, Result};
 use hyprland::data::FullscreenMode;
 use hyprland::data::Monitor;
 use hyprland::data::Monitors;
-use hyprland::dispatch::MonitorIdentifier;
-use hyprland::dispatch::WorkspaceIdentifierWithSpecial;
+use hyprland::data::Transforms;
 use hyprland::{
     data::{Client, Clients, CursorPosition},
     dispatch::{Dispatch, DispatchType, WindowIdentifier},
     shared::{HyprData, HyprDataActiveOptional},
 };
+use serde_json::Value;
 use tokio::sync::broadcast;
 use tokio::sync::mpsc;
+use tracing::{debug, warn};
 
+use crate::animation::KeywordAnimator;
+use crate::command::format_template;
 use crate::command::Command;
+use crate::command::QueryCommand;
+use crate::command::ToggleAction;
+use crate::config::parse_modifiers;
+use crate::config::AnimationMode;
 use crate::config::Config;
-use crate::event::set_workspace_anim;
-use crate::event::Animation;
+use crate::config::Transition;
+use crate::error::ErrorKind;
+use crate::error::KoolError;
 use crate::event::KEvent;
+use crate::hyprctl::current_submap;
+use crate::hyprctl::DispatchBatch;
 use crate::info::ActivityStatus;
+use crate::info::BatchStepResult;
+use crate::info::EdgeSwitchStatus;
 use crate::info::KInfoEvent;
+use crate::info::KoolEvent;
 use crate::info::MonitorStatus;
 use crate::info::SubmapStatus;
 use crate::info::WindowStatus;
+use crate::info::WorkspaceQuery;
 use crate::info::WorkspaceStatus;
+use crate::plugin::Animation;
+use crate::plugin::Capability;
+use crate::plugin::PluginClient;
 
 pub struct State {
     pub config: Config,
     pub monitors: Vec<KMonitor>,
     pub harpoon_map: HashMap<String, String>,
+    pub edge_switch_enabled: bool,
+    pub plugin: PluginClient,
+    keyword_anim: KeywordAnimator,
+
+    /// (monitor name -> active workspace) last reported in events
+    last_active_workspaces: HashMap<String, String>,
+    /// focused monitor last reported in events
+    last_focused_monitor: Option<String>,
 }
 
 impl State {
     pub async fn new(config: Config) -> Result<Self> {
         let m = Monitors::get_async().await?;
-        let monitors = m
+        Ok(Self::with_monitors(config, m.into_iter().collect()))
+    }
+
+    fn with_monitors(config: Config, monitors: Vec<Monitor>) -> Self {
+        let monitors = monitors
             .into_iter()
             .map(|m| KMonitor::new(m, &config.activities))
             .collect();
 
-        Ok(Self {
+        let mut state = Self {
+            edge_switch_enabled: config.daemon.mouse.switch_workspace_on_edge,
+            plugin: PluginClient::new(Duration::from_millis(config.daemon.ipc_timeout)),
+            keyword_anim: Default::default(),
             config,
             monitors,
             harpoon_map: Default::default(),
-        })
+            last_active_workspaces: Default::default(),
+            last_focused_monitor: None,
+        };
+        _ = state.workspace_events();
+        state
+    }
+
+    /// animation for the next workspace switch. `to` is the workspace being switched to (if known)
+    async fn set_anim(&mut self, anim: Animation, to: Option<&str>) {
+        let use_plugin = match self.config.animations.mode {
+            AnimationMode::Off => return,
+            AnimationMode::Plugin => true,
+            AnimationMode::Keyword => false,
+            AnimationMode::Auto => self.plugin.supports(Capability::SetAnimation).await,
+        };
+        let duration = self.config.animations.duration;
+        let curve = self.config.animations.curve.clone();
+        if use_plugin {
+            _ = self.plugin.set_animation(anim, duration, curve).await;
+        } else {
+            let from = self
+                .monitors
+                .iter()
+                .find(|m| m.monitor.focused)
+                .map(|m| m.monitor.active_workspace.name.clone());
+            let switch = from.as_deref().zip(to);
+            if let Err(e) = self.keyword_anim.set(anim, duration, curve, switch).await {
+                warn!("could not set workspace animation: {:?}", e);
+            }
+        }
+    }
+
+    /// undoes hyprkool's changes to hyprland's config. for when the daemon exits
+    pub async fn restore(&mut self) {
+        if let Err(e) = self.keyword_anim.restore().await {
+            warn!("could not restore workspace animation: {:?}", e);
+        }
+    }
+
+    /// animation for a transition into this activity that moves (x, y) (see [`Self::movement_to`]).
+    /// `to` is the workspace being switched to (if known)
+    async fn animate(
+        &mut self,
+        transition: Transition,
+        activity: Option<&str>,
+        x: i32,
+        y: i32,
+        to: Option<&str>,
+    ) {
+        let anim = self
+            .config
+            .animations
+            .get(transition, activity)
+            .animation(x, y);
+        self.set_anim(anim, to).await;
+    }
+
+    /// (x, y) movement from the focused workspace to this one.
+    /// changing activities moves along x (in the order of config.activities)
+    fn movement_to(&self, activity: &str, ws: Option<KWorkspace>) -> (i32, i32) {
+        let Some((a, current)) = self
+            .monitors
+            .iter()
+            .find(|m| m.monitor.focused)
+            .and_then(|m| m.current())
+        else {
+            return (0, 0);
+        };
+        if a == activity {
+            return match ws {
+                Some(ws) => (ws.x - current.x, ws.y - current.y),
+                None => (0, 0),
+            };
+        }
+        let index = |name: &str| self.config.activities.iter().position(|n| n == name);
+        match (index(&a), index(activity)) {
+            (Some(from), Some(to)) => (to as i32 - from as i32, 0),
+            _ => (0, 0),
+        }
+    }
+
+    /// what changed since the last time this was called
+    fn workspace_events(&mut self) -> Vec<KoolEvent> {
+        let mut events = vec![];
+
+        for m in self.monitors.iter().filter(|m| !m.monitor.disabled) {
+            let to = &m.monitor.active_workspace.name;
+            let Some(from) = self
+                .last_active_workspaces
+                .insert(m.monitor.name.clone(), to.clone())
+            else {
+                continue;
+            };
+            if &from == to {
+                continue;
+            }
+
+            let activity = |ws: &str| {
+                KWorkspace::from_ws_name(ws)
+                    .and(KActivity::from_ws_name(ws))
+                    .map(|a| a.name)
+            };
+            let (from_a, to_a) = (activity(&from), activity(to));
+            if from_a != to_a {
+                events.push(KoolEvent::ActivityChanged {
+                    monitor: m.monitor.name.clone(),
+                    from: from_a,
+                    to: to_a,
+                });
+            }
+            events.push(KoolEvent::WorkspaceChanged {
+                monitor: m.monitor.name.clone(),
+                from,
+                to: to.clone(),
+            });
+        }
+
+        let focused = self
+            .monitors
+            .iter()
+            .find(|m| m.monitor.focused)
+            .map(|m| m.monitor.name.clone());
+        if focused != self.last_focused_monitor {
+            let from = std::mem::replace(&mut self.last_focused_monitor, focused.clone());
+            if let Some(to) = focused {
+                events.push(KoolEvent::MonitorFocused { from, to });
+            }
+        }
+
+        events
     }
 
     fn moved_ws(&self, ws: KWorkspace, wrap: bool, x: i32, y: i32) -> KWorkspace {
@@ -104,22 +266,35 @@ impl State {
         move_window: bool,
     ) -> Result<()> {
         let cursor = CursorPosition::get_async().await?;
+        let window = window_to_move(move_window).await?;
+        self.move_monitor_to_valid_activity_batch(name, window.as_ref(), (cursor.x, cursor.y))
+            .send()
+            .await
+    }
 
+    /// `window` is moved along if it is not None. cursor is put back at `cursor` (x, y)
+    fn move_monitor_to_valid_activity_batch(
+        &mut self,
+        name: &str,
+        window: Option<&Client>,
+        cursor: (i64, i64),
+    ) -> DispatchBatch {
         let mut taken = HashSet::new();
 
         for m in self.monitors.iter() {
             if m.monitor.disabled {
                 if m.monitor.name == name {
-                    return Ok(());
+                    return DispatchBatch::default();
                 }
                 continue;
             }
             taken.insert(m.monitor.active_workspace.name.clone());
             if m.monitor.name == name && m.current().is_some() {
-                return Ok(());
+                return DispatchBatch::default();
             }
         }
 
+        let mut batch = DispatchBatch::default();
         'outer: for a in self.config.activities.iter() {
             for y in 1..=self.config.workspaces.1 {
                 for x in 1..=self.config.workspaces.0 {
@@ -131,7 +306,7 @@ impl State {
                         if m.monitor.name != name {
                             continue;
                         }
-                        m.move_to(a.into(), ws, move_window).await?;
+                        m.move_to_batched(a.into(), ws, window, &mut batch);
                         break 'outer;
                     }
                 }
@@ -139,23 +314,29 @@ impl State {
         }
 
         // focus the monitor that was focused before moving the other monitor to another ws
-        Dispatch::call_async(DispatchType::Custom(
-            "focusmonitor",
-            &self.focused_monitor_mut().monitor.name.to_string(),
-        ))
-        .await?;
-        Dispatch::call_async(DispatchType::MoveCursor(cursor.x, cursor.y)).await?;
-
-        Ok(())
+        batch
+            .dispatch(
+                "focusmonitor",
+                &self.focused_monitor_mut().monitor.name.to_string(),
+            )
+            .dispatch("movecursor", &format!("{} {}", cursor.0, cursor.1));
+        batch
     }
 
     async fn move_towards(&mut self, x: i32, y: i32, cycle: bool, move_window: bool) -> Result<()> {
         let (a, ws) = self
             .focused_monitor_mut()
             .current()
-            .context("not in a hyprkool workspace")?;
+            .ok_or_else(KoolError::not_in_kool_workspace)?;
         let ws = self.moved_ws(ws, cycle, x, y);
-        _ = KWorkspace::set_anim(x, y).await;
+        self.animate(
+            Transition::GridMove,
+            Some(&a),
+            x,
+            y,
+            Some(&ws.name(&a, false)),
+        )
+        .await;
         self.focused_monitor_mut()
             .move_to(a, ws, move_window)
             .await?;
@@ -177,6 +358,12 @@ impl State {
         } else {
             self.config.activities[0].clone()
         };
+        let to = self
+            .focused_monitor_mut()
+            .activity_workspace(&a)
+            .name(&a, false);
+        self.animate(Transition::ActivityChange, Some(&a), z, 0, Some(&to))
+            .await;
         self.focused_monitor_mut()
             .move_to_activity(a, move_window)
             .await?;
@@ -198,22 +385,120 @@ impl State {
         }
         let m = &mut self.monitors[mi as usize];
         let name = m.monitor.active_workspace.name.clone();
-        if move_window {
-            m.move_focused_window_to_raw(&name).await?;
-        }
-        _ = set_workspace_anim(Animation::Fade).await;
-        Dispatch::call_async(DispatchType::FocusMonitor(MonitorIdentifier::Name(
-            &m.monitor.name,
-        )))
-        .await?;
+        let monitor = m.monitor.name.clone();
+        let activity = m.current().map(|(a, _)| a);
+        let window = window_to_move(move_window).await?;
+        let mut batch = DispatchBatch::default();
+        m.move_focused_window_to_raw(&name, window.as_ref(), &mut batch);
+        self.animate(Transition::MonitorChange, activity.as_deref(), z, 0, None)
+            .await;
+        batch.dispatch("focusmonitor", &monitor);
+        batch.send().await?;
         Ok(())
     }
 
+    /// moves `window` (the focused one) in or out of the special workspace.
+    /// `windows` are all the windows
+    fn toggle_special_workspace_batch(
+        &mut self,
+        name: &str,
+        silent: bool,
+        window: &Client,
+        windows: &[Client],
+    ) -> DispatchBatch {
+        let special_workspace = format!("special:{}", name);
+        let active_workspace = self
+            .focused_monitor_mut()
+            .monitor
+            .active_workspace
+            .name
+            .clone();
+
+        let mut batch = DispatchBatch::default();
+        if window.workspace.name == special_workspace {
+            let c = windows
+                .iter()
+                .filter(|w| w.workspace.id == window.workspace.id)
+                .count();
+            if silent && c != 1 {
+                batch.dispatch(
+                    "movetoworkspacesilent",
+                    &format!("name:{}", active_workspace),
+                );
+            } else {
+                // keep focus if moving the last window from special to active workspace
+                self.focused_monitor_mut().move_focused_window_to_raw(
+                    &active_workspace,
+                    Some(window),
+                    &mut batch,
+                );
+                batch
+                    .dispatch("focusworkspaceoncurrentmonitor", &active_workspace)
+                    .dispatch("focuswindow", &format!("address:{}", window.address));
+            }
+        } else {
+            batch.dispatch("movetoworkspacesilent", &special_workspace);
+            if !silent {
+                batch.dispatch("togglespecialworkspace", name);
+            }
+        }
+        batch
+    }
+
     pub async fn execute(
         &mut self,
         command: Command,
         tx: Option<mpsc::Sender<KEvent>>,
-    ) -> Result<()> {
+    ) -> Result<Option<Value>> {
+        match command {
+            Command::Batch {
+                commands,
+                stop_on_error,
+            } => {
+                let mut results = vec![];
+                for command in Command::parse_batch(&commands)? {
+                    let res = match &command {
+                        Command::Batch { .. } => Err(anyhow!("batches can't be nested")),
+                        _ => self.execute_command(command.clone(), tx.clone()).await,
+                    };
+                    let failed = res.is_err();
+                    results.push(match res {
+                        Ok(data) => BatchStepResult {
+                            command,
+                            ok: true,
+                            error: None,
+                            kind: None,
+                            data,
+                        },
+                        Err(e) => {
+                            let e = KoolError::from_anyhow(&e, ErrorKind::Rejected);
+                            BatchStepResult {
+                                command,
+                                ok: false,
+                                error: Some(e.message),
+                                kind: Some(e.kind),
+                                data: None,
+                            }
+                        }
+                    });
+                    if failed && stop_on_error {
+                        break;
+                    }
+
+                    // next command should see the effects of this one
+                    self.update_monitors().await?;
+                }
+                Ok(Some(serde_json::to_value(results)?))
+            }
+            command => self.execute_command(command, tx).await,
+        }
+    }
+
+    async fn execute_command(
+        &mut self,
+        command: Command,
+        tx: Option<mpsc::Sender<KEvent>>,
+    ) -> Result<Option<Value>> {
         match command {
             Command::MoveRight { cycle, move_window } => {
                 self.move_towards(1, 0, cycle, move_window).await?;
@@ -238,81 +523,41 @@ impl State {
                 move_window,
                 silent,
             } => {
-                _ = set_workspace_anim(Animation::Fade).await;
+                self.set_anim(Animation::Fade, None).await;
                 if !move_window {
                     Dispatch::call_async(DispatchType::ToggleSpecialWorkspace(Some(name))).await?;
-                    return Ok(());
+                    return Ok(None);
                 }
                 let window = Client::get_active_async()
                     .await?
                     .context("No active window")?;
-
-                let special_workspace = format!("special:{}", &name);
-                let active_workspace = self
-                    .focused_monitor_mut()
-                    .monitor
-                    .active_workspace
-                    .name
-                    .clone();
-
-                if window.workspace.name == special_workspace {
-                    if silent {
-                        let windows = Clients::get_async().await?;
-                        let c = windows
-                            .iter()
-                            .filter(|w| w.workspace.id == window.workspace.id)
-                            .count();
-                        if c == 1 {
-                            // keep focus if moving the last window from special to active workspace
-                            self.focused_monitor_mut()
-                                .move_focused_window_to_raw(&active_workspace)
-                                .await?;
-                            Dispatch::call_async(DispatchType::Custom(
-                                "focusworkspaceoncurrentmonitor",
-                                &active_workspace,
-                            ))
-                            .await?;
-                            Dispatch::call_async(DispatchType::FocusWindow(
-                                WindowIdentifier::Address(window.address),
-                            ))
-                            .await?;
-                        } else {
-                            Dispatch::call_async(DispatchType::MoveToWorkspaceSilent(
-                                WorkspaceIdentifierWithSpecial::Name(&active_workspace),
-                                None,
-                            ))
-                            .await?;
-                        }
-                    } else {
-                        self.focused_monitor_mut()
-                            .move_focused_window_to_raw(&active_workspace)
-                            .await?;
-                        Dispatch::call_async(DispatchType::Custom(
-                            "focusworkspaceoncurrentmonitor",
-                            &active_workspace,
-                        ))
-                        .await?;
-                        Dispatch::call_async(DispatchType::FocusWindow(WindowIdentifier::Address(
-                            window.address,
-                        )))
-                        .await?;
-                    }
-                } else {
-                    Dispatch::call_async(DispatchType::MoveToWorkspaceSilent(
-                        WorkspaceIdentifierWithSpecial::Special(Some(&name)),
-                        None,
-                    ))
-                    .await?;
-                    if !silent {
-                        Dispatch::call_async(DispatchType::ToggleSpecialWorkspace(Some(name)))
-                            .await?;
-                    }
-                };
+                let windows = Clients::get_async().await?.into_iter().collect::<Vec<_>>();
+                let batch = self.toggle_special_workspace_batch(&name, silent, &window, &windows);
+                batch.send().await?;
             }
             Command::ToggleOverview => {
+                self.set_anim(Animation::Fade, None).await;
                 self.focused_monitor_mut().toggle_overview().await?;
             }
+            Command::EdgeSwitch { action } => {
+                let Some(tx) = &tx else {
+                    return Err(anyhow!("edge switching needs the daemon running"));
+                };
+                self.edge_switch_enabled = match action {
+                    ToggleAction::Enable => true,
+                    ToggleAction::Disable => false,
+                    ToggleAction::Toggle => !self.edge_switch_enabled,
+                };
+                tx.send(KEvent::EdgeSwitchInfoRequested).await?;
+            }
             Command::SwitchToActivity { name, move_window } => {
+                let (x, y) = self.movement_to(&name, None);
+                let to = self
+                    .focused_monitor_mut()
+                    .activity_workspace(&name)
+                    .name(&name, false);
+                self.animate(Transition::ActivityChange, Some(&name), x, y, Some(&to))
+                    .await;
                 self.focused_monitor_mut()
                     .move_to_activity(name, move_window)
                     .await?;
@@ -332,10 +577,18 @@ impl State {
                 let (a, _ws) = self
                     .focused_monitor_mut()
                     .current()
-                    .context("not in hyprkool activity")?;
+                    .ok_or_else(KoolError::not_in_kool_workspace)?;
                 let ws =
                     KWorkspace::from_ws_part_of_name(&name).context("invalid workspace name")?;
-                _ = set_workspace_anim(Animation::Fade).await;
+                let (x, y) = self.movement_to(&a, Some(ws));
+                self.animate(
+                    Transition::GridMove,
+                    Some(&a),
+                    x,
+                    y,
+                    Some(&ws.name(&a, false)),
+                )
+                .await;
                 self.focused_monitor_mut()
                     .move_to(a, ws, move_window)
                     .await?;
@@ -343,7 +596,13 @@ impl State {
             Command::SwitchToWorkspace { name, move_window } => {
                 let a = KActivity::from_ws_name(&name).context("activity not found")?;
                 let ws = KWorkspace::from_ws_name(&name).context("workspace not found")?;
-                _ = set_workspace_anim(Animation::Fade).await;
+                let (x, y) = self.movement_to(&a.name, Some(ws));
+                let transition = match self.focused_monitor_mut().current() {
+                    Some((current, _)) if current == a.name => Transition::GridMove,
+                    _ => Transition::ActivityChange,
+                };
+                self.animate(transition, Some(&a.name), x, y, Some(&name))
+                    .await;
                 self.focused_monitor_mut()
                     .move_to(a.name, ws, move_window)
                     .await?;
@@ -360,14 +619,15 @@ impl State {
                     .iter()
                     .find(|m| m.monitor.name == name)
                     .context("monitor with provided name does not exist")?;
-                if move_window {
-                    m.move_focused_window_to_raw(&m.monitor.active_workspace.name)
-                        .await?;
-                }
-                Dispatch::call_async(DispatchType::FocusMonitor(MonitorIdentifier::Name(
-                    &m.monitor.name,
-                )))
-                .await?;
+                let window = window_to_move(move_window).await?;
+                let mut batch = DispatchBatch::default();
+                m.move_focused_window_to_raw(
+                    &m.monitor.active_workspace.name,
+                    window.as_ref(),
+                    &mut batch,
+                );
+                batch.dispatch("focusmonitor", &m.monitor.name);
+                batch.send().await?;
             }
             Command::SwapMonitorsActiveWorkspace {
                 monitor_1,
@@ -375,7 +635,7 @@ impl State {
                 move_window,
             } => {
                 if monitor_1 == monitor_2 && monitor_1.is_some() {
-                    return Ok(());
+                    return Ok(None);
                 }
                 let (monitor_1, monitor_2) = match (monitor_1, monitor_2) {
                     (None, Some(_)) | (Some(_), None) => {
@@ -430,12 +690,11 @@ impl State {
                         .iter_mut()
                         .find(|m| m.monitor.name == monitor_1)
                         .expect("won't get here if it's none");
-                    m_1.move_to_raw(&ws2, false).await?;
+                    let mut batch = DispatchBatch::default();
+                    m_1.move_to_raw_batched(&ws2, None, &mut batch);
 
-                    Dispatch::call_async(DispatchType::FocusMonitor(MonitorIdentifier::Name(
-                        &self.focused_monitor_mut().monitor.name,
-                    )))
-                    .await?;
+                    batch.dispatch("focusmonitor", &self.focused_monitor_mut().monitor.name);
+                    batch.send().await?;
                 }
             }
             Command::SetNamedFocus { name } => {
@@ -445,24 +704,40 @@ impl State {
                     .active_workspace
                     .name
                     .clone();
-                if self
+                let workspace = if self
                     .harpoon_map
                     .get(&name)
                     .map(|ws| &w == ws)
                     .unwrap_or_default()
                 {
                     self.harpoon_map.remove(&name);
+                    None
                 } else {
-                    self.harpoon_map.insert(name, w);
-                }
+                    self.harpoon_map.insert(name.clone(), w.clone());
+                    Some(w)
+                };
                 if let Some(tx) = &tx {
                     tx.send(KEvent::MonitorInfoRequested).await?;
+                    tx.send(KEvent::Kool(KoolEvent::NamedFocusSet { name, workspace }))
+                        .await?;
                 }
             }
             Command::SwitchNamedFocus { name, move_window } => {
                 match self.harpoon_map.get(&name).cloned() {
                     Some(ws) => {
-                        _ = set_workspace_anim(Animation::Fade).await;
+                        let activity = KActivity::from_ws_name(&ws).map(|a| a.name);
+                        let (x, y) = match &activity {
+                            Some(a) => self.movement_to(a, KWorkspace::from_ws_name(&ws)),
+                            None => (0, 0),
+                        };
+                        self.animate(
+                            Transition::HarpoonJump,
+                            activity.as_deref(),
+                            x,
+                            y,
+                            Some(&ws),
+                        )
+                        .await;
                         self.focused_monitor_mut()
                             .move_to_raw(&ws, move_window)
                             .await?;
@@ -470,22 +745,124 @@ impl State {
                     None => return Err(anyhow!("no workspace set to the provided name")),
                 }
             }
-            Command::Daemon | Command::DaemonQuit | Command::Info { .. } => {
-                return Err(anyhow!("Can't run this command here"))
+            Command::Query { query, format } => {
+                let value = self.query(query)?;
+                let value = match format {
+                    Some(template) => Value::String(format_template(&template, &value)),
+                    None => value,
+                };
+                return Ok(Some(value));
+            }
+            Command::PluginLoaded => {
+                self.plugin.invalidate();
+                self.plugin.detect().await;
+            }
+            Command::MouseScroll { x, y } => {
+                if let Some(reason) = self.edge_switch_blocked().await? {
+                    return Err(KoolError::new(ErrorKind::Rejected, reason).into());
+                }
+                let monitor = self.focused_monitor_mut();
+                let name = monitor.monitor.name.clone();
+                let (a, ws) = monitor
+                    .current()
+                    .ok_or_else(KoolError::not_in_kool_workspace)?;
+                let new_ws = self.moved_ws(ws, true, x, y);
+                if new_ws != ws {
+                    let from = ws.name(&a, false);
+                    let to = new_ws.name(&a, false);
+                    self.move_towards(x, y, true, false).await?;
+                    if let Some(tx) = &tx {
+                        tx.send(KEvent::Kool(KoolEvent::EdgeSwitched {
+                            monitor: name,
+                            from,
+                            to,
+                        }))
+                        .await?;
+                    }
+                }
             }
+            Command::Daemon
+            | Command::DaemonQuit
+            | Command::Status
+            | Command::Info { .. }
+            | Command::Shell
+            | Command::Batch { .. } => return Err(anyhow!("Can't run this command here")),
         }
 
-        Ok(())
+        Ok(None)
+    }
+
+    fn query_workspace(&self, workspace: &str) -> WorkspaceQuery {
+        let mut named_focus = self
+            .harpoon_map
+            .iter()
+            .filter(|(_, ws)| ws.as_str() == workspace)
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        named_focus.sort();
+
+        let ws = KWorkspace::from_ws_name(workspace);
+        WorkspaceQuery {
+            workspace: workspace.to_string(),
+            activity: KActivity::from_ws_name(workspace)
+                .filter(|_| ws.is_some())
+                .map(|a| a.name),
+            x: ws.map(|ws| ws.x),
+            y: ws.map(|ws| ws.y),
+            monitor: self
+                .monitors
+                .iter()
+                .find(|m| !m.monitor.disabled && m.monitor.active_workspace.name == workspace)
+                .map(|m| m.monitor.name.clone()),
+            named_focus,
+        }
+    }
+
+    fn query(&mut self, query: QueryCommand) -> Result<Value> {
+        let value = match query {
+            QueryCommand::Current => {
+                let m = self.focused_monitor_mut();
+                m.current().ok_or_else(KoolError::not_in_kool_workspace)?;
+                let ws = m.monitor.active_workspace.name.clone();
+                serde_json::to_value(self.query_workspace(&ws))?
+            }
+            QueryCommand::NamedFocus { name } => {
+                let ws = self
+                    .harpoon_map
+                    .get(&name)
+                    .context("no workspace set to the provided name")?;
+                serde_json::to_value(self.query_workspace(ws))?
+            }
+            QueryCommand::NamedFocii => {
+                let mut workspaces = self.harpoon_map.values().cloned().collect::<Vec<_>>();
+                workspaces.sort();
+                workspaces.dedup();
+                serde_json::to_value(
+                    workspaces
+                        .iter()
+                        .map(|ws| self.query_workspace(ws))
+                        .collect::<Vec<_>>(),
+                )?
+            }
+        };
+        Ok(value)
     }
 
     #[allow(clippy::single_match)]
     pub async fn update(&mut self, event: KEvent, tx: broadcast::Sender<KInfoEvent>) -> Result<()> {
         self.update_monitors().await?;
 
-        println!("{:?}", &event);
+        debug!(?event, "hyprland event");
+
+        for e in self.workspace_events() {
+            tx.send(KInfoEvent::Event(e))?;
+        }
 
         match &event {
             KEvent::MonitorAdded { name } => {
+                tx.send(KInfoEvent::Event(KoolEvent::MonitorAdded {
+                    monitor: name.clone(),
+                }))?;
                 if self.config.daemon.move_monitors_to_hyprkool_activity {
                     self.move_monitor_to_valid_activity(name, false).await?;
                 }
@@ -507,13 +884,44 @@ impl State {
                         .min_by_key(|c| c.focus_history_id);
                     if let Some(w) = w {
                         let c = CursorPosition::get_async().await?;
-                        Dispatch::call_async(DispatchType::FocusWindow(WindowIdentifier::Address(
-                            w.address.clone(),
-                        )))
-                        .await?;
-                        Dispatch::call_async(DispatchType::MoveCursor(c.x, c.y)).await?;
+                        let mut batch = DispatchBatch::default();
+                        batch
+                            .dispatch("focuswindow", &format!("address:{}", w.address))
+                            .dispatch("movecursor", &format!("{} {}", c.x, c.y));
+                        batch.send().await?;
+                    }
+                }
+            }
+            KEvent::WindowChange | KEvent::EdgeSwitchInfoRequested => {
+                tx.send(KInfoEvent::EdgeSwitch(self.edge_switch_status().await?))?;
+            }
+            KEvent::MonitorRemoved { name } => {
+                tx.send(KInfoEvent::Event(KoolEvent::MonitorRemoved {
+                    monitor: name.clone(),
+                }))?;
+            }
+            KEvent::ConfigReloaded => {
+                // plugins might have been (un)loaded
+                self.plugin.invalidate();
+                self.keyword_anim.invalidate();
+            }
+            KEvent::HyprlandReconnected => {
+                // monitors might have been plugged in while we were not listening
+                if self.config.daemon.move_monitors_to_hyprkool_activity {
+                    for name in self
+                        .monitors
+                        .iter()
+                        .filter(|m| !m.monitor.disabled)
+                        .map(|m| m.monitor.name.clone())
+                        .collect::<Vec<_>>()
+                    {
+                        self.move_monitor_to_valid_activity(&name, false).await?;
+                        self.update_monitors().await?;
                     }
                 }
+                tx.send(KInfoEvent::EdgeSwitch(self.edge_switch_status().await?))?;
+                let submap = current_submap().await?;
+                tx.send(KInfoEvent::Submap(SubmapStatus { submap }))?;
             }
             _ => {}
         }
@@ -526,20 +934,104 @@ impl State {
             | KEvent::WindowClosed
             | KEvent::WorkspaceChange
             | KEvent::MonitorAdded { .. }
-            | KEvent::MonitorRemoved { .. } => {
+            | KEvent::MonitorRemoved { .. }
+            | KEvent::HyprlandReconnected => {
                 let clients = Clients::get_async().await?.into_iter().collect::<Vec<_>>();
                 tx.send(KInfoEvent::Monitors(self.gather_info(&clients)))?;
             }
             KEvent::Submap { name } => {
+                tx.send(KInfoEvent::Event(KoolEvent::SubmapChanged {
+                    submap: name.clone(),
+                }))?;
                 tx.send(KInfoEvent::Submap(SubmapStatus { submap: name }))?;
             }
-            KEvent::MonitorChange { .. } => {}
+            KEvent::Kool(e) => {
+                tx.send(KInfoEvent::Event(e))?;
+            }
+            KEvent::SubmapInfoRequested => {
+                let submap = current_submap().await?;
+                tx.send(KInfoEvent::Submap(SubmapStatus { submap }))?;
+            }
+            KEvent::MonitorChange { .. }
+            | KEvent::EdgeSwitchInfoRequested
+            | KEvent::ConfigReloaded => {}
         }
         Ok(())
     }
 
-    pub async fn tick(&mut self) -> Result<()> {
-        if !self.config.daemon.mouse.switch_workspace_on_edge {
+    fn edge_switch_inhibitor(&self, window: Option<&Client>) -> Option<String> {
+        let window = window?;
+        let mouse = &self.config.daemon.mouse;
+        if mouse.inhibit_classes.iter().any(|c| c == &window.class)
+            || mouse
+                .inhibit_titles
+                .iter()
+                .any(|t| window.title.contains(t))
+        {
+            return Some(window.class.clone());
+        }
+        None
+    }
+
+    async fn edge_switch_status(&self) -> Result<EdgeSwitchStatus> {
+        let window = Client::get_active_async().await?;
+        let inhibited_by = self.edge_switch_inhibitor(window.as_ref());
+        Ok(EdgeSwitchStatus {
+            enabled: self.edge_switch_enabled,
+            active: self.edge_switch_enabled && inhibited_by.is_none(),
+            inhibited_by,
+        })
+    }
+
+    async fn modifiers_allow_edge_switch(&mut self) -> Result<bool> {
+        let mouse = &self.config.daemon.mouse;
+        if mouse.require_modifiers.is_none() && mouse.suppress_modifiers.is_none() {
+            return Ok(true);
+        }
+
+        let Some(held) = self.plugin.modifiers().await.unwrap_or_default() else {
+            // can't know what is held without the plugin
+            return Ok(mouse.require_modifiers.is_none());
+        };
+
+        if let Some(mods) = &mouse.require_modifiers {
+            let mods = parse_modifiers(mods)?;
+            if held & mods != mods {
+                return Ok(false);
+            }
+        }
+        if let Some(mods) = &mouse.suppress_modifiers {
+            let mods = parse_modifiers(mods)?;
+            if held & mods == mods {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// why the screen edges (and scrolls on them) should not switch workspaces right now
+    async fn edge_switch_blocked(&mut self) -> Result<Option<String>> {
+        if !self.edge_switch_enabled {
+            return Ok(Some("edge switching is disabled".into()));
+        }
+        if let Some(window) = Client::get_active_async().await? {
+            // should i use window.fullscreen or window.fullscreen_client ?
+            if window.fullscreen as u8 > FullscreenMode::Maximized as u8 {
+                return Ok(Some("a window is fullscreen".into()));
+            }
+            if let Some(class) = self.edge_switch_inhibitor(Some(&window)) {
+                return Ok(Some(format!("edge switching is inhibited by {}", class)));
+            }
+        }
+        if !self.modifiers_allow_edge_switch().await? {
+            return Ok(Some("the held modifiers don't allow edge switching".into()));
+        }
+        Ok(None)
+    }
+
+    pub async fn tick(&mut self, tx: broadcast::Sender<KInfoEvent>) -> Result<()> {
+        if !self.edge_switch_enabled {
             return Ok(());
         }
 
@@ -552,76 +1044,84 @@ impl State {
         let mut c = CursorPosition::get_async().await?;
         let monitor = self.focused_monitor_mut();
 
-        // OOF:
-        // hyprland returns wrong scale.
-        // hyprland seems to support only a few scales (ig depending on the screen resolution)
-        //  but it returns only 2 decimal places of the scale. which hurts calculation precision.
-        //  not sure what to do here.
-        let mut scale = monitor.monitor.scale;
-        if scale == 0.83 {
-            scale = 0.8333333;
-        }
-        let width = (monitor.monitor.width as f64 / scale as f64) as i64;
-        let height = (monitor.monitor.height as f64 / scale as f64) as i64;
+        let (width, height) = monitor.logical_size();
 
         c.x -= monitor.monitor.x as i64;
         c.y -= monitor.monitor.y as i64;
 
-        // dbg!(&c, width, height, scale);
-
         let mut y: i64 = 0;
         let mut x: i64 = 0;
-        let mut anim = Animation::Fade;
+        // direction of the switch
+        let mut dx = 0;
+        let mut dy = 0;
         if c.x <= w {
             x += nx - 1;
             c.x = width - m;
-            anim = Animation::Left;
+            dx = -1;
         } else if c.x >= width - 1 - w {
             x += 1;
             c.x = m;
-            anim = Animation::Right;
+            dx = 1;
         }
         if c.y <= w {
             y += ny - 1;
             c.y = height - m;
-            anim = Animation::Up;
+            dy = -1;
         } else if c.y >= height - 1 - w {
             y += 1;
             c.y = m;
-            anim = Animation::Down;
+            dy = 1;
         }
 
         if x + y == 0 {
             return Ok(());
         }
 
-        if x > 0 && y > 0 {
-            anim = Animation::Fade;
-        }
-
         c.x += monitor.monitor.x as i64;
         c.y += monitor.monitor.y as i64;
 
         let Some((a, ws)) = monitor.current() else {
-            println!(
+            debug!(
                 "not in a hyprkool workspace: {}",
                 monitor.monitor.active_workspace.name
             );
             return Ok(());
         };
 
-        if let Some(window) = Client::get_active_async().await? {
-            // should i use window.fullscreen or window.fullscreen_client ?
-            if window.fullscreen as u8 > FullscreenMode::Maximized as u8 {
-                return Ok(());
-            }
+        if self.edge_switch_blocked().await?.is_some() {
+            return Ok(());
         }
 
         let new_ws = self.moved_ws(ws, true, x as _, y as _);
         if new_ws != ws {
-            _ = set_workspace_anim(anim).await;
-            self.focused_monitor_mut().move_to(a, new_ws, false).await?;
-            Dispatch::call_async(DispatchType::MoveCursor(c.x, c.y)).await?;
+            let dragged = if self.config.daemon.mouse.move_dragged_window {
+                self.plugin.dragged_window().await.unwrap_or_default()
+            } else {
+                None
+            };
+
+            let from = ws.name(&a, false);
+            let to = new_ws.name(&a, false);
+            self.animate(Transition::EdgeSwitch, Some(&a), dx, dy, Some(&to))
+                .await;
+            let mut batch = DispatchBatch::default();
+            if let Some(address) = dragged {
+                batch.dispatch(
+                    "movetoworkspacesilent",
+                    &format!("name:{},address:{}", to, address),
+                );
+            }
+            let monitor = self.focused_monitor_mut();
+            let name = monitor.monitor.name.clone();
+            monitor.move_to_batched(a, new_ws, None, &mut batch);
+            batch.dispatch("movecursor", &format!("{} {}", c.x, c.y));
+            batch.send().await?;
+
+            tx.send(KInfoEvent::Event(KoolEvent::EdgeSwitched {
+                monitor: name,
+                from,
+                to,
+            }))?;
         }
         Ok(())
     }
@@ -718,6 +1218,58 @@ impl KMonitor {
     fn get_activity_index(&self, name: &str) -> Option<usize> {
         self.activities.iter().position(|a| a.name == name)
     }
+
+    /// size of the monitor in layout coordinates (the ones cursor position is reported in)
+    fn logical_size(&self) -> (i64, i64) {
+        logical_size(
+            self.monitor.width as i64,
+            self.monitor.height as i64,
+            self.monitor.scale as f64,
+            &self.monitor.transform,
+        )
+    }
+}
+
+fn logical_size(width: i64, height: i64, scale: f64, transform: &Transforms) -> (i64, i64) {
+    let scale = exact_scale(scale, width, height);
+    let width = (width as f64 / scale).round() as i64;
+    let height = (height as f64 / scale).round() as i64;
+
+    // width and height are of the unrotated mode
+    match transform {
+        Transforms::Normal90
+        | Transforms::Normal270
+        | Transforms::Flipped90
+        | Transforms::Flipped270 => (height, width),
+        _ => (width, height),
+    }
+}
+
+/// hyprland reports scale rounded to 2 decimal places. but it only ever uses scales that are
+/// multiples of 1/120 (wayland fractional scale), preferring the ones that divide the resolution cleanly.
+/// so we can recover the exact scale from the rounded one.
+fn exact_scale(scale: f64, width: i64, height: i64) -> f64 {
+    let is_clean = |s: f64, px: i64| {
+        let l = px as f64 / s;
+        (l - l.round()).abs() < 0.01
+    };
+
+    let k = (scale * 120.0).round();
+    let mut closest = None;
+    for k in [k, k - 1.0, k + 1.0] {
+        let s = k / 120.0;
+        if k <= 0.0 || (s - scale).abs() > 0.005 + 1e-9 {
+            continue;
+        }
+        if is_clean(s, width) && is_clean(s, height) {
+            return s;
+        }
+        if closest.is_none() {
+            closest = Some(s);
+        }
+    }
+
+    closest.unwrap_or(scale)
 }
 
 // assuming self is updated with most recent info
@@ -729,79 +1281,78 @@ impl KMonitor {
         Some((a.name, w))
     }
 
-    async fn move_to_activity(&mut self, activity: String, move_window: bool) -> Result<()> {
-        _ = set_workspace_anim(Animation::Fade).await;
-
+    /// workspace that switching to this activity goes to
+    fn activity_workspace(&self, activity: &str) -> KWorkspace {
         if let Some(ws) = self
-            .get_activity_index(&activity)
+            .get_activity_index(activity)
             .and_then(|i| self.activities[i].last_workspace.as_ref())
             .copied()
         {
-            self.move_to(activity, ws, move_window).await?;
+            ws
         } else if let Some((_, ws)) = self.current() {
-            self.move_to(activity, ws, move_window).await?;
+            ws
         } else {
-            self.move_to(activity, KWorkspace { x: 1, y: 1 }, move_window)
-                .await?;
+            KWorkspace { x: 1, y: 1 }
         }
+    }
 
-        Ok(())
+    async fn move_to_activity(&mut self, activity: String, move_window: bool) -> Result<()> {
+        let ws = self.activity_workspace(&activity);
+        self.move_to(activity, ws, move_window).await
     }
 
     async fn toggle_overview(&mut self) -> Result<()> {
-        let (a, ws) = self.current().context("not in a hyprkool activity")?;
-
-        _ = set_workspace_anim(Animation::Fade).await;
+        let (a, ws) = self
+            .current()
+            .ok_or_else(KoolError::not_in_kool_workspace)?;
 
+        let mut batch = DispatchBatch::default();
         if !self.monitor.focused {
-            Dispatch::call_async(DispatchType::Custom(
-                "focusmonitor",
-                &format!("{}", self.monitor.id),
-            ))
-            .await?;
+            batch.dispatch("focusmonitor", &format!("{}", self.monitor.id));
         }
 
         if self.monitor.active_workspace.name.ends_with(":overview") {
-            Dispatch::call_async(DispatchType::Custom(
+            batch.dispatch(
                 "focusworkspaceoncurrentmonitor",
                 &format!("name:{}", ws.name(&a, false)),
-            ))
-            .await?;
+            );
         } else {
-            Dispatch::call_async(DispatchType::Workspace(
-                WorkspaceIdentifierWithSpecial::Name(&ws.name(&a, true)),
-            ))
-            .await?;
+            batch.dispatch("workspace", &format!("name:{}", ws.name(&a, true)));
         }
+        batch.send().await?;
 
         Ok(())
     }
 
     async fn move_to_raw(&mut self, ws_name: &str, move_window: bool) -> Result<()> {
+        let window = window_to_move(move_window).await?;
+        let mut batch = DispatchBatch::default();
+        self.move_to_raw_batched(ws_name, window.as_ref(), &mut batch);
+        batch.send().await
+    }
+
+    /// `window` is moved along if it is not None
+    fn move_to_raw_batched(
+        &mut self,
+        ws_name: &str,
+        window: Option<&Client>,
+        batch: &mut DispatchBatch,
+    ) {
         if let Some((a, ws)) = self.current() {
             if let Some(ai) = self.get_activity_index(&a) {
                 self.activities[ai].last_workspace = Some(ws);
             }
         };
 
-        if move_window {
-            self.move_focused_window_to_raw(ws_name).await?;
-        }
+        self.move_focused_window_to_raw(ws_name, window, batch);
 
         if !self.monitor.focused {
-            Dispatch::call_async(DispatchType::Custom(
-                "focusmonitor",
-                &format!("{}", self.monitor.id),
-            ))
-            .await?;
+            batch.dispatch("focusmonitor", &format!("{}", self.monitor.id));
         }
-        Dispatch::call_async(DispatchType::Custom(
+        batch.dispatch(
             "focusworkspaceoncurrentmonitor",
             &format!("name:{}", ws_name),
-        ))
-        .await?;
-
-        Ok(())
+        );
     }
 
     async fn move_to(
@@ -810,55 +1361,41 @@ impl KMonitor {
         new_ws: KWorkspace,
         move_window: bool,
     ) -> Result<()> {
-        if let Some((a, ws)) = self.current() {
-            if let Some(ai) = self.get_activity_index(&a) {
-                self.activities[ai].last_workspace = Some(ws);
-            }
-        };
-
-        if move_window {
-            self.move_focused_window_to(&activity, new_ws).await?;
-        }
-
-        if !self.monitor.focused {
-            Dispatch::call_async(DispatchType::Custom(
-                "focusmonitor",
-                &format!("{}", self.monitor.id),
-            ))
-            .await?;
-        }
-        Dispatch::call_async(DispatchType::Custom(
-            "focusworkspaceoncurrentmonitor",
-            &format!("name:{}", new_ws.name(&activity, false)),
-        ))
-        .await?;
-
-        Ok(())
+        let window = window_to_move(move_window).await?;
+        let mut batch = DispatchBatch::default();
+        self.move_to_batched(activity, new_ws, window.as_ref(), &mut batch);
+        batch.send().await
     }
 
-    async fn move_focused_window_to(&self, activity: &str, ws: KWorkspace) -> Result<()> {
-        if let Some(_window) = Client::get_active_async().await? {
-            Dispatch::call_async(DispatchType::MoveToWorkspaceSilent(
-                WorkspaceIdentifierWithSpecial::Name(&ws.name(activity, false)),
-                None,
-            ))
-            .await?;
-        }
-
-        Ok(())
+    fn move_to_batched(
+        &mut self,
+        activity: String,
+        new_ws: KWorkspace,
+        window: Option<&Client>,
+        batch: &mut DispatchBatch,
+    ) {
+        self.move_to_raw_batched(&new_ws.name(&activity, false), window, batch)
     }
 
-    async fn move_focused_window_to_raw(&self, ws: &str) -> Result<()> {
-        if let Some(_window) = Client::get_active_async().await? {
-            Dispatch::call_async(DispatchType::MoveToWorkspaceSilent(
-                WorkspaceIdentifierWithSpecial::Name(ws),
-                None,
-            ))
-            .await?;
+    /// `window` is the focused window (if there is one)
+    fn move_focused_window_to_raw(
+        &self,
+        ws: &str,
+        window: Option<&Client>,
+        batch: &mut DispatchBatch,
+    ) {
+        if window.is_some() {
+            batch.dispatch("movetoworkspacesilent", &format!("name:{}", ws));
         }
+    }
+}
 
-        Ok(())
+/// the focused window if it should be moved along with the workspace switch
+async fn window_to_move(move_window: bool) -> Result<Option<Client>> {
+    if !move_window {
+        return Ok(None);
     }
+    Ok(Client::get_active_async().await?)
 }
 
 #[derive(Clone, Debug)]
@@ -905,21 +1442,234 @@ impl KWorkspace {
             format!("{}:({} {})", activity, self.x, self.y)
         }
     }
+}
 
-    async fn set_anim(x: i32, y: i32) -> Result<()> {
-        if x > 0 && y == 0 {
-            return set_workspace_anim(Animation::Right).await;
-        }
-        if x < 0 && y == 0 {
-            return set_workspace_anim(Animation::Left).await;
-        }
-        if x == 0 && y > 0 {
-            return set_workspace_anim(Animation::Down).await;
-        }
-        if x == 0 && y < 0 {
-            return set_workspace_anim(Animation::Up).await;
-        }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_scale_recovers_fractional_scales() {
+        assert_eq!(exact_scale(0.83, 1920, 1080), 100.0 / 120.0);
+        assert_eq!(exact_scale(1.6, 2560, 1600), 192.0 / 120.0);
+        assert_eq!(exact_scale(1.33, 3840, 2160), 160.0 / 120.0);
+        assert_eq!(exact_scale(1.67, 2880, 1800), 200.0 / 120.0);
+        assert_eq!(exact_scale(1.0, 1920, 1080), 1.0);
+        assert_eq!(exact_scale(2.0, 3840, 2160), 2.0);
+    }
+
+    #[test]
+    fn logical_size_of_scaled_monitors() {
+        let normal = Transforms::Normal;
+        assert_eq!(logical_size(1920, 1080, 0.83, &normal), (2304, 1296));
+        assert_eq!(logical_size(2560, 1600, 1.6, &normal), (1600, 1000));
+        assert_eq!(logical_size(3840, 2160, 1.33, &normal), (2880, 1620));
+        assert_eq!(logical_size(2880, 1800, 1.67, &normal), (1728, 1080));
+        assert_eq!(logical_size(1920, 1080, 1.0, &normal), (1920, 1080));
+    }
+
+    #[test]
+    fn logical_size_of_rotated_monitors() {
+        assert_eq!(
+            logical_size(2560, 1600, 1.6, &Transforms::Normal90),
+            (1000, 1600)
+        );
+        assert_eq!(
+            logical_size(1920, 1080, 0.83, &Transforms::Flipped270),
+            (1296, 2304)
+        );
+        assert_eq!(
+            logical_size(3840, 2160, 1.33, &Transforms::Normal180),
+            (2880, 1620)
+        );
+    }
+
+    /// same shape as `hyprctl monitors -j`
+    fn monitor(id: i64, name: &str, focused: bool, ws_id: i64, ws_name: &str) -> Monitor {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": name,
+            "description": "",
+            "make": "",
+            "model": "",
+            "serial": "",
+            "width": 1920,
+            "height": 1080,
+            "physicalWidth": 0,
+            "physicalHeight": 0,
+            "refreshRate": 60.0,
+            "x": 1920 * id,
+            "y": 0,
+            "activeWorkspace": { "id": ws_id, "name": ws_name },
+            "specialWorkspace": { "id": 0, "name": "" },
+            "reserved": [0, 0, 0, 0],
+            "scale": 1.0,
+            "transform": 0,
+            "focused": focused,
+            "dpmsStatus": true,
+            "vrr": false,
+            "solitary": "0",
+            "activelyTearing": false,
+            "directScanoutTo": "0",
+            "disabled": false,
+            "currentFormat": "XRGB8888",
+            "mirrorOf": "none",
+            "availableModes": ["1920x1080@60.00Hz"],
+        }))
+        .expect("invalid monitor")
+    }
+
+    /// same shape as `hyprctl clients -j`
+    fn client(address: &str, ws_id: i64, ws_name: &str) -> Client {
+        serde_json::from_value(serde_json::json!({
+            "address": address,
+            "mapped": true,
+            "hidden": false,
+            "at": [0, 0],
+            "size": [800, 600],
+            "workspace": { "id": ws_id, "name": ws_name },
+            "floating": false,
+            "pseudo": false,
+            "monitor": 0,
+            "class": "kitty",
+            "title": "kitty",
+            "initialClass": "kitty",
+            "initialTitle": "kitty",
+            "pid": 1234,
+            "xwayland": false,
+            "pinned": false,
+            "fullscreen": 0,
+            "fullscreenClient": 0,
+            "grouped": [],
+            "tags": [],
+            "swallowing": "0x0",
+            "focusHistoryID": 0,
+            "inhibitingIdle": false,
+            "xdgTag": "",
+            "xdgDescription": "",
+        }))
+        .expect("invalid client")
+    }
+
+    /// DP-1 (focused) is on main:(1 1), HDMI-A-1 is on a workspace that is not a hyprkool workspace
+    fn state() -> State {
+        let config = Config {
+            activities: vec!["main".into(), "work".into()],
+            workspaces: (2, 2),
+            ..Default::default()
+        };
+        State::with_monitors(
+            config,
+            vec![
+                monitor(0, "DP-1", true, 1, "main:(1 1)"),
+                monitor(1, "HDMI-A-1", false, 2, "2"),
+            ],
+        )
+    }
+
+    #[test]
+    fn toggle_special_workspace_moves_window_in() {
+        let mut state = state();
+        let window = client("0x1", 1, "main:(1 1)");
+        let windows = [window.clone()];
+
+        let batch = state.toggle_special_workspace_batch("scratch", true, &window, &windows);
+        assert_eq!(
+            batch.commands(),
+            ["dispatch movetoworkspacesilent special:scratch"]
+        );
+
+        let batch = state.toggle_special_workspace_batch("scratch", false, &window, &windows);
+        assert_eq!(
+            batch.commands(),
+            [
+                "dispatch movetoworkspacesilent special:scratch",
+                "dispatch togglespecialworkspace scratch",
+            ]
+        );
+    }
+
+    #[test]
+    fn toggle_special_workspace_silently_moves_window_out() {
+        let mut state = state();
+        let window = client("0x1", -98, "special:scratch");
+
+        // other windows stay in the special workspace, so it stays open
+        let windows = [window.clone(), client("0x2", -98, "special:scratch")];
+        let batch = state.toggle_special_workspace_batch("scratch", true, &window, &windows);
+        assert_eq!(
+            batch.commands(),
+            ["dispatch movetoworkspacesilent name:main:(1 1)"]
+        );
+
+        // last window keeps focus
+        let windows = [window.clone(), client("0x2", 1, "main:(1 1)")];
+        let batch = state.toggle_special_workspace_batch("scratch", true, &window, &windows);
+        assert_eq!(
+            batch.commands(),
+            [
+                "dispatch movetoworkspacesilent name:main:(1 1)",
+                "dispatch focusworkspaceoncurrentmonitor main:(1 1)",
+                "dispatch focuswindow address:0x1",
+            ]
+        );
+    }
+
+    #[test]
+    fn move_monitor_to_valid_activity_picks_first_free_workspace() {
+        let mut state = state();
+        let window = client("0x1", 1, "main:(1 1)");
+
+        let batch =
+            state.move_monitor_to_valid_activity_batch("HDMI-A-1", Some(&window), (100, 200));
+        assert_eq!(
+            batch.commands(),
+            [
+                "dispatch movetoworkspacesilent name:main:(2 1)",
+                "dispatch focusmonitor 1",
+                "dispatch focusworkspaceoncurrentmonitor name:main:(2 1)",
+                "dispatch focusmonitor DP-1",
+                "dispatch movecursor 100 200",
+            ]
+        );
+
+        // already in a hyprkool workspace
+        let batch = state.move_monitor_to_valid_activity_batch("DP-1", None, (100, 200));
+        assert!(batch.is_empty());
+    }
 
-        set_workspace_anim(Animation::Fade).await
+    #[test]
+    fn move_to_with_window() {
+        let mut state = state();
+        let window = client("0x1", 1, "main:(1 1)");
+
+        let m = state.focused_monitor_mut();
+        let mut batch = DispatchBatch::default();
+        m.move_to_batched(
+            "main".into(),
+            KWorkspace { x: 2, y: 1 },
+            Some(&window),
+            &mut batch,
+        );
+        assert_eq!(
+            batch.commands(),
+            [
+                "dispatch movetoworkspacesilent name:main:(2 1)",
+                "dispatch focusworkspaceoncurrentmonitor name:main:(2 1)",
+            ]
+        );
+        // to come back to it when switching back to this activity
+        assert_eq!(
+            m.activities[0].last_workspace,
+            Some(KWorkspace { x: 1, y: 1 })
+        );
+
+        // without a window
+        let mut batch = DispatchBatch::default();
+        m.move_to_batched("work".into(), KWorkspace { x: 1, y: 2 }, None, &mut batch);
+        assert_eq!(
+            batch.commands(),
+            ["dispatch focusworkspaceoncurrentmonitor name:work:(1 2)"]
+        );
     }
 }
@@ -0,0 +1,383 @@
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::Context as LayerContext;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use crate::event::VERSION;
+use crate::hyprctl;
+use crate::plugin::PluginClient;
+use crate::plugin::PluginStatus;
+
+/// hyprland versions the plugin of this hyprkool version is tested with
+/// (keep in sync with the compatibility table in README.md)
+pub const TESTED_HYPRLAND_VERSIONS: &[&str] = &["0.51.0"];
+
+/// how many errors the daemon remembers for `hyprkool status`
+const RECENT_ERRORS: usize = 20;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RecentError {
+    /// unix timestamp in seconds
+    pub time: u64,
+    pub message: String,
+}
+
+/// errors the daemon ran into. nobody is watching the daemon's output, so the last few are kept around.
+/// this is a tracing layer that remembers every error level event.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorLog {
+    errors: Arc<Mutex<VecDeque<RecentError>>>,
+}
+
+impl ErrorLog {
+    fn record(&self, message: String) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut errors = self.errors.lock().expect("error log lock poisoned");
+        if errors.len() == RECENT_ERRORS {
+            errors.pop_front();
+        }
+        errors.push_back(RecentError { time, message });
+    }
+
+    pub fn recent(&self) -> Vec<RecentError> {
+        let errors = self.errors.lock().expect("error log lock poisoned");
+        errors.iter().cloned().collect()
+    }
+}
+
+impl<S> Layer<S> for ErrorLog
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = MessageVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(SpanFields(visitor.fields));
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: LayerContext<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut visitor = MessageVisitor::default();
+        values.record(&mut visitor);
+        let mut extensions = span.extensions_mut();
+        if let Some(fields) = extensions.get_mut::<SpanFields>() {
+            fields.0 += &visitor.fields;
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: LayerContext<'_, S>) {
+        if *event.metadata().level() != Level::ERROR {
+            return;
+        }
+
+        // eg. 'ipc{id=1 command=MoveRight}: could not move'
+        let mut message = String::new();
+        for span in ctx
+            .event_scope(event)
+            .into_iter()
+            .flat_map(|s| s.from_root())
+        {
+            message += span.name();
+            if let Some(fields) = span.extensions().get::<SpanFields>() {
+                if !fields.0.is_empty() {
+                    message += &format!("{{{}}}", fields.0.trim_start());
+                }
+            }
+            message += ": ";
+        }
+        let mut visitor = MessageVisitor::default();
+        event.record(&mut visitor);
+        message += &visitor.message;
+        message += &visitor.fields;
+
+        self.record(message);
+    }
+}
+
+/// fields of a span, formatted like ' id=1 command=MoveRight'.
+/// (fmt layers keep their own formatted fields, but there might not be one. eg. when logging to journald)
+struct SpanFields(String);
+
+#[derive(Default)]
+struct MessageVisitor {
+    message: String,
+    fields: String,
+}
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            self.fields += &format!(" {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DaemonStatus {
+    pub version: String,
+    pub pid: u32,
+    /// in seconds
+    pub uptime: u64,
+    /// None if the daemon runs with the default config
+    pub config_path: Option<PathBuf>,
+    /// connections listening to `hyprkool info` commands
+    pub info_clients: usize,
+    /// oldest first
+    pub recent_errors: Vec<RecentError>,
+}
+
+impl DaemonStatus {
+    pub fn new(
+        started: Instant,
+        config_path: Option<PathBuf>,
+        info_clients: usize,
+        errors: &ErrorLog,
+    ) -> Self {
+        Self {
+            version: VERSION.to_string(),
+            pid: std::process::id(),
+            uptime: started.elapsed().as_secs(),
+            config_path,
+            info_clients,
+            recent_errors: errors.recent(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HyprlandStatus {
+    pub version: String,
+    /// if the plugin of this hyprkool version is tested with this hyprland version
+    pub tested: bool,
+}
+
+impl HyprlandStatus {
+    pub async fn get() -> Result<Self> {
+        let version = hyprctl::request("j/version").await?;
+        let version: serde_json::Value =
+            serde_json::from_str(&version).context("could not parse hyprland version")?;
+        let tag = version
+            .get("tag")
+            .and_then(|t| t.as_str())
+            .context("hyprland did not report a version tag")?;
+        Ok(Self {
+            version: tag.to_string(),
+            tested: is_tested_hyprland_version(tag),
+        })
+    }
+}
+
+/// tags look like 'v0.51.0' or 'v0.51.0-12-gdeadbeef' for builds from git
+fn is_tested_hyprland_version(tag: &str) -> bool {
+    let version = tag.trim_start_matches('v');
+    let version = version.split('-').next().unwrap_or(version);
+    TESTED_HYPRLAND_VERSIONS.contains(&version)
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Status {
+    /// version of the cli
+    pub version: String,
+    /// None if the daemon is not running
+    pub daemon: Option<DaemonStatus>,
+    /// as seen by the daemon if it is running
+    pub plugin: PluginStatus,
+    /// None if hyprland could not be reached
+    pub hyprland: Option<HyprlandStatus>,
+}
+
+impl Status {
+    /// `timeout` is how long the caller waits for the status. the plugin and hyprland get half of it,
+    /// so that a plugin that does not reply does not look like an unreachable daemon.
+    pub async fn new(
+        daemon: Option<DaemonStatus>,
+        plugin: &mut PluginClient,
+        timeout: Duration,
+    ) -> Self {
+        let budget = timeout / 2;
+
+        // the plugin might have been loaded or unloaded since it was last checked
+        plugin.invalidate();
+        let (plugin, hyprland) = tokio::join!(
+            tokio::time::timeout(budget, plugin.detect()),
+            tokio::time::timeout(budget, HyprlandStatus::get()),
+        );
+
+        Self {
+            version: VERSION.to_string(),
+            daemon,
+            plugin: plugin.cloned().unwrap_or(PluginStatus::Unknown),
+            hyprland: hyprland.ok().and_then(Result::ok),
+        }
+    }
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "hyprkool {}", self.version)?;
+
+        match &self.daemon {
+            Some(d) => {
+                write!(
+                    f,
+                    "daemon: running (version {}, pid {}, up {})",
+                    d.version,
+                    d.pid,
+                    format_duration(d.uptime)
+                )?;
+                if d.version != self.version {
+                    write!(f, ". version differs from the cli, restart the daemon")?;
+                }
+                writeln!(f)?;
+                match &d.config_path {
+                    Some(p) => writeln!(f, "config: {}", p.display())?,
+                    None => writeln!(f, "config: default (no config file)")?,
+                }
+                writeln!(f, "info clients: {}", d.info_clients)?;
+            }
+            None => writeln!(f, "daemon: not running")?,
+        }
+
+        match &self.plugin {
+            PluginStatus::Unknown => writeln!(f, "plugin: unknown (it did not reply in time)")?,
+            PluginStatus::Absent => writeln!(f, "plugin: not loaded")?,
+            PluginStatus::Incompatible(e) => writeln!(f, "plugin: incompatible ({})", e)?,
+            PluginStatus::Loaded(info) => writeln!(f, "plugin: loaded (version {})", info.version)?,
+        }
+
+        match &self.hyprland {
+            Some(h) if h.tested => writeln!(f, "hyprland: {}", h.version)?,
+            Some(h) => writeln!(
+                f,
+                "hyprland: {} (the plugin is only tested with {})",
+                h.version,
+                TESTED_HYPRLAND_VERSIONS.join(", ")
+            )?,
+            None => writeln!(f, "hyprland: not reachable")?,
+        }
+
+        if let Some(d) = &self.daemon {
+            if d.recent_errors.is_empty() {
+                writeln!(f, "recent errors: none")?;
+            } else {
+                writeln!(f, "recent errors:")?;
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0);
+                for e in &d.recent_errors {
+                    writeln!(
+                        f,
+                        "  [{} ago] {}",
+                        format_duration(now.saturating_sub(e.time)),
+                        e.message
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// eg. '1h 2m 3s'
+fn format_duration(secs: u64) -> String {
+    let (h, m, s) = (secs / 3600, (secs / 60) % 60, secs % 60);
+    match (h, m) {
+        (0, 0) => format!("{}s", s),
+        (0, _) => format!("{}m {}s", m, s),
+        _ => format!("{}h {}m {}s", h, m, s),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::plugin::mock::{hello, MockPlugin};
+    use crate::plugin::PLUGIN_PROTOCOL_VERSION;
+
+    #[tokio::test]
+    async fn probes_plugin_again() {
+        // an older plugin was loaded when the daemon checked. it has been reloaded since
+        let mock = MockPlugin::new(
+            "status",
+            &[
+                &hello(PLUGIN_PROTOCOL_VERSION + 1),
+                &hello(PLUGIN_PROTOCOL_VERSION),
+            ],
+        );
+        let mut plugin = mock.client();
+        assert!(matches!(
+            plugin.detect().await,
+            PluginStatus::Incompatible(_)
+        ));
+
+        let status = Status::new(None, &mut plugin, Duration::from_secs(2)).await;
+        assert!(matches!(status.plugin, PluginStatus::Loaded(_)));
+        assert_eq!(mock.requests().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn plugin_that_does_not_reply() {
+        let mock = MockPlugin::silent("stuck");
+
+        let mut plugin = PluginClient::with_socket(mock.socket(), Duration::from_secs(10));
+        let timeout = Duration::from_millis(400);
+        let started = Instant::now();
+        let status = Status::new(None, &mut plugin, timeout).await;
+        assert!(started.elapsed() < timeout);
+        assert_eq!(status.plugin, PluginStatus::Unknown);
+
+        mock.stop();
+    }
+
+    #[test]
+    fn error_log_keeps_span_fields() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        // no fmt layer, like when logging to journald
+        let errors = ErrorLog::default();
+        let subscriber = tracing_subscriber::registry().with(errors.clone());
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("ipc", id = 3, command = tracing::field::Empty);
+            span.record("command", "MoveRight");
+            let _guard = span.enter();
+            tracing::warn!("not remembered");
+            tracing::error!(reason = "gone", "could not move");
+        });
+
+        let recent = errors.recent();
+        assert_eq!(recent.len(), 1);
+        assert_eq!(
+            recent[0].message,
+            r#"ipc{id=3 command="MoveRight"}: could not move reason="gone""#
+        );
+    }
+
+    #[test]
+    fn tested_hyprland_versions() {
+        assert!(is_tested_hyprland_version("v0.51.0"));
+        assert!(is_tested_hyprland_version("v0.51.0-12-gdeadbeef"));
+        assert!(!is_tested_hyprland_version("v0.40.0"));
+    }
+}
@@ -0,0 +1,133 @@
+use std::os::fd::FromRawFd;
+use std::os::linux::net::SocketAddrExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+
+use anyhow::{anyhow, Context, Result};
+use tracing::warn;
+
+// - [sd_listen_fds](https://www.freedesktop.org/software/systemd/man/latest/sd_listen_fds.html)
+// - [sd_notify](https://www.freedesktop.org/software/systemd/man/latest/sd_notify.html)
+
+/// the first fd passed by socket activation
+const LISTEN_FDS_START: i32 = 3;
+
+/// the listening socket if systemd started us through socket activation.
+/// this changes the env, so it must be called before any other threads are started.
+pub fn listen_fds() -> Result<Option<std::os::unix::net::UnixListener>> {
+    let pid = std::env::var("LISTEN_PID").ok();
+    let fds = std::env::var("LISTEN_FDS").ok();
+
+    // these are meant for this process only
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+
+    let (Some(pid), Some(fds)) = (pid, fds) else {
+        return Ok(None);
+    };
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        return Ok(None);
+    }
+    let fds = fds
+        .parse::<i32>()
+        .with_context(|| format!("invalid LISTEN_FDS: {}", fds))?;
+    if fds == 0 {
+        return Ok(None);
+    }
+    if fds > 1 {
+        return Err(anyhow!(
+            "expected 1 socket from systemd, got {}. hyprkool only listens on one socket",
+            fds
+        ));
+    }
+
+    let fd = LISTEN_FDS_START;
+    // SAFETY: stat is plain old data. fstat only writes to it
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } != 0 {
+        return Err(std::io::Error::last_os_error()).context("invalid socket from systemd");
+    }
+    if stat.st_mode & libc::S_IFMT != libc::S_IFSOCK {
+        return Err(anyhow!("fd {} from systemd is not a socket", fd));
+    }
+    // SAFETY: systemd passed this fd to us (LISTEN_PID is our pid) and nothing else owns it
+    let listener = unsafe {
+        // don't leak it into processes we spawn
+        libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        std::os::unix::net::UnixListener::from_raw_fd(fd)
+    };
+    Ok(Some(listener))
+}
+
+/// sends a state to the service manager. does nothing if we are not running under systemd
+/// eg. "READY=1" or "STATUS=moving monitors"
+pub fn notify(state: &str) -> Result<()> {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return Ok(());
+    };
+    let path = path
+        .into_string()
+        .map_err(|p| anyhow!("invalid NOTIFY_SOCKET: {:?}", p))?;
+    notify_to(&path, state)
+}
+
+/// sends a state to the socket at this path (in the format of NOTIFY_SOCKET)
+fn notify_to(path: &str, state: &str) -> Result<()> {
+    // '@' is for sockets in the abstract namespace
+    let addr = match path.strip_prefix('@') {
+        Some(name) => SocketAddr::from_abstract_name(name)?,
+        None => SocketAddr::from_pathname(path)?,
+    };
+    let sock = UnixDatagram::unbound()?;
+    sock.send_to_addr(state.as_bytes(), &addr)
+        .with_context(|| format!("could not notify systemd at {}", path))?;
+    Ok(())
+}
+
+fn notify_or_warn(state: &str) {
+    if let Err(e) = notify(state) {
+        warn!("{:?}", e);
+    }
+}
+
+/// startup is done
+pub fn ready(status: &str) {
+    notify_or_warn(&ready_state(status));
+}
+
+fn ready_state(status: &str) -> String {
+    format!("READY=1\nSTATUS={}", status)
+}
+
+/// shows up in `systemctl --user status`
+pub fn status(status: &str) {
+    notify_or_warn(&format!("STATUS={}", status));
+}
+
+pub fn stopping() {
+    notify_or_warn("STOPPING=1");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn notifies_readiness() {
+        let dir = std::env::temp_dir().join(format!("hyprkool-test-{}-notify", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let sock = UnixDatagram::bind(&path).unwrap();
+
+        notify_to(path.to_str().unwrap(), &ready_state("running")).unwrap();
+
+        let mut buf = [0; 64];
+        sock.set_read_timeout(Some(std::time::Duration::from_secs(1)))
+            .unwrap();
+        let n = sock.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"READY=1\nSTATUS=running");
+
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}